@@ -0,0 +1,203 @@
+//! Compare `CPU::step` against the old loop, which decoded and resolved all three
+//! parameters of every instruction before looking at the opcode, and against the
+//! pre-decoding `FastCPU`.
+//!
+//! Run with `cargo bench --bench step`.
+
+use std::time::{Duration, Instant};
+
+use advent_of_code_2019::intcode::asm::assemble;
+use advent_of_code_2019::intcode::error::{IntcodeError, Location};
+use advent_of_code_2019::intcode::fast::FastCPU;
+use advent_of_code_2019::intcode::memory::Memory;
+use advent_of_code_2019::intcode::{read_program, OpCodeMode, Parameter, Status, CPU};
+
+/// The step loop as it was before opcodes drove operand decoding.
+struct Eager {
+    mem: Memory,
+    pc: usize,
+    inputs: Vec<i64>,
+    relative_base: i64,
+}
+
+impl Eager {
+    fn new(program: &[i64], inputs: &[i64]) -> Eager {
+        Eager {
+            mem: Memory::new(program),
+            pc: 0,
+            inputs: inputs.to_vec(),
+            relative_base: 0,
+        }
+    }
+
+    fn step(&mut self) -> Result<Status<i64>, IntcodeError> {
+        loop {
+            let at = Location {
+                pc: self.pc,
+                instruction: self.mem.get(self.pc).unwrap_or(0),
+                relative_base: self.relative_base,
+            };
+            let code = self
+                .mem
+                .get(self.pc)
+                .ok_or(IntcodeError::PcOutOfBounds { at })?;
+            let modes: OpCodeMode = code.into();
+
+            let param = |offset: usize, mode: u8| {
+                let raw = self
+                    .mem
+                    .get(self.pc + offset)
+                    .ok_or(IntcodeError::PcOutOfBounds { at })?;
+                Parameter::build(mode, raw).ok_or(IntcodeError::BadParameterMode { mode, at })
+            };
+            let p1 = param(1, modes.p1);
+            let p2 = param(2, modes.p2);
+            let p3 = param(3, modes.p3);
+
+            let real_p1 = p1
+                .clone()
+                .and_then(|p| p.realize(&self.mem, self.relative_base, at));
+            let real_p2 = p2
+                .clone()
+                .and_then(|p| p.realize(&self.mem, self.relative_base, at));
+            let _real_p3 = p3
+                .clone()
+                .and_then(|p| p.realize(&self.mem, self.relative_base, at));
+
+            let write_p1 = p1.and_then(|p| p.realize_write(self.relative_base, false, at));
+            let _write_p2 = p2.and_then(|p| p.realize_write(self.relative_base, false, at));
+            let write_p3 = p3.and_then(|p| p.realize_write(self.relative_base, false, at));
+
+            match modes.opcode {
+                1 | 2 | 7 | 8 => {
+                    self.pc += 4;
+                    let (a, b) = (real_p1?, real_p2?);
+                    let value = match modes.opcode {
+                        1 => a + b,
+                        2 => a * b,
+                        7 => (a < b) as i64,
+                        _ => (a == b) as i64,
+                    };
+                    self.mem.set(write_p3?, value);
+                }
+                3 => {
+                    if self.inputs.is_empty() {
+                        return Ok(Status::NeedsInput);
+                    }
+                    self.pc += 2;
+                    let inp = self.inputs[0];
+                    self.mem.set(write_p1?, inp);
+                    self.inputs = self.inputs.iter().skip(1).copied().collect();
+                }
+                4 => {
+                    self.pc += 2;
+                    return Ok(Status::Ready(real_p1?));
+                }
+                5 | 6 => {
+                    self.pc += 3;
+                    let (test, target) = (real_p1?, real_p2?);
+                    if (test != 0) == (modes.opcode == 5) {
+                        self.pc = target as usize;
+                    }
+                }
+                9 => {
+                    self.pc += 2;
+                    self.relative_base += real_p1?;
+                }
+                99 => return Ok(Status::Halted),
+                _ => {
+                    return Err(IntcodeError::UnknownOpcode {
+                        opcode: code % 100,
+                        at,
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn run_eager(program: &[i64], inputs: &[i64]) -> Vec<i64> {
+    let mut cpu = Eager::new(program, inputs);
+    let mut outputs = Vec::new();
+    while let Status::Ready(value) = cpu.step().unwrap() {
+        outputs.push(value);
+    }
+    outputs
+}
+
+fn run_lazy(program: &[i64], inputs: &[i64]) -> Vec<i64> {
+    let mut cpu = CPU::new(program);
+    for input in inputs {
+        cpu.add_input(*input);
+    }
+    let mut outputs = Vec::new();
+    while let Status::Ready(value) = cpu.step().unwrap() {
+        outputs.push(value);
+    }
+    outputs
+}
+
+fn run_fast(program: &[i64], inputs: &[i64]) -> Vec<i64> {
+    let mut cpu = FastCPU::new(program);
+    for input in inputs {
+        cpu.add_input(*input);
+    }
+    let mut outputs = Vec::new();
+    while let Status::Ready(value) = cpu.step().unwrap() {
+        outputs.push(value);
+    }
+    outputs
+}
+
+/// Best of `runs` timings, after checking all loops agree.
+fn bench(name: &str, runs: u32, program: &[i64], inputs: &[i64]) {
+    let expected = run_eager(program, inputs);
+    assert_eq!(expected, run_lazy(program, inputs));
+    assert_eq!(expected, run_fast(program, inputs));
+
+    let time = |run: fn(&[i64], &[i64]) -> Vec<i64>| {
+        (0..runs)
+            .map(|_| {
+                let start = Instant::now();
+                run(program, inputs);
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or_default()
+    };
+    let eager = time(run_eager);
+    let lazy = time(run_lazy);
+    let fast = time(run_fast);
+
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    println!(
+        "{:<12} eager {:>9.3}ms  lazy {:>9.3}ms {:>5.2}x  fast {:>9.3}ms {:>5.2}x",
+        name,
+        ms(eager),
+        ms(lazy),
+        ms(eager) / ms(lazy),
+        ms(fast),
+        ms(eager) / ms(fast)
+    );
+}
+
+fn main() {
+    // Count to a million, the kind of tight loop long running programs spend their time in.
+    let count = assemble(
+        "
+        loop:   ADD [count], #1 -> [count]
+                LT [count], [limit] -> [flag]
+                JNZ [flag], #loop
+                OUT [count]
+                HLT
+        count:  DB 0
+        limit:  DB 1000000
+        flag:   DB 0
+        ",
+    )
+    .unwrap();
+    bench("count", 10, &count, &[]);
+
+    let day9 = read_program("input/day9.txt").unwrap();
+    bench("day9 part 2", 10, &day9, &[2]);
+}
@@ -0,0 +1,48 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, Result};
+
+use advent_of_code_2019::intcode::ascii::End;
+use advent_of_code_2019::intcode::{read_program, CPU};
+
+/// Drive an ASCII Intcode program from the terminal, e.g. `cargo run --bin ascii input/day25.txt`.
+///
+/// Lines typed in are fed to the program, outputs outside the ASCII range are
+/// printed as numbers.
+fn main() -> Result<()> {
+    let path = env::args().nth(1).context("usage: ascii <program>")?;
+    let mut cpu = CPU::new(&read_program(&path)?);
+
+    let stdin = io::stdin();
+    loop {
+        let text = cpu.read_text()?;
+        match text.end {
+            End::Newline => println!("{}", text.text),
+            End::Value(value) => {
+                if !text.text.is_empty() {
+                    println!("{}", text.text);
+                }
+                println!("{}", value);
+            }
+            End::NeedsInput => {
+                print!("{}", text.text);
+                io::stdout().flush()?;
+
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line)? == 0 {
+                    break;
+                }
+                cpu.add_line(&line);
+            }
+            End::Halted => {
+                if !text.text.is_empty() {
+                    println!("{}", text.text);
+                }
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
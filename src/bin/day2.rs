@@ -1,78 +1,56 @@
-use std::fs;
-
 use anyhow::{Context, Result};
 
-fn read_input() -> Result<Vec<usize>> {
-    let contents = fs::read_to_string("input/day2.txt")?;
-    contents
-        .split(',')
-        .map(|s| Ok(s.parse::<usize>()?))
-        .collect()
-}
-
-fn run(noun: usize, verb: usize, data: &[usize]) -> Option<Vec<usize>> {
-    let mut program = data.to_owned();
-    program[1] = noun;
-    program[2] = verb;
-
-    for index in (0..program.len()).step_by(4) {
-        let opcode = *program.get(index)?;
-
-        if opcode == 99 {
-            break;
-        }
+use advent_of_code_2019::intcode::patch::Patch;
+use advent_of_code_2019::intcode::symbolic::{self, Method, Observe, Unknown};
+use advent_of_code_2019::intcode::{read_program, CPU};
 
-        let read_pos_a = *program.get(index + 1)?;
-        let read_pos_b = *program.get(index + 2)?;
-        let out_pos = *program.get(index + 3)?;
-
-        if out_pos >= program.len() {
-            return None;
-        }
+fn read_input() -> Result<Vec<i64>> {
+    read_program("input/day2.txt")
+}
 
-        let a = program.get(read_pos_a)?;
-        let b = program.get(read_pos_b)?;
+fn run(patch: &Patch, data: &[i64]) -> Result<Vec<i64>> {
+    let mut cpu = CPU::new(data);
+    cpu.apply_patch(patch);
+    cpu.run_to_completion()?;
 
-        match opcode {
-            1 => program[out_pos] = a + b,
-            2 => program[out_pos] = a * b,
-            _ => return None,
-        }
-    }
-
-    Some(program)
+    Ok(cpu.memory().to_vec())
 }
 
 #[test]
-fn test_run() {
+fn test_run() -> Result<()> {
     let test = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
-    let result = run(9, 10, &test);
-    assert_eq!(
-        vec!(3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50),
-        result.unwrap()
-    );
+    let result = run(&Patch::new("noun/verb").set(1, 9).set(2, 10), &test)?;
+    assert_eq!(vec!(3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50), result);
+    Ok(())
 }
 
-fn solve(noun: usize, verb: usize, data: &[usize]) -> Option<usize> {
-    run(noun, verb, data).map(|program| program[0])
+fn solve(patch: &Patch, data: &[i64]) -> Result<i64> {
+    run(patch, data).map(|program| program[0])
 }
 
 fn main() -> Result<()> {
     let input = read_input()?;
 
-    let part1 = solve(12, 2, &input).context("failed to find a solution")?;
+    let alarm = Patch::load_named("input/day2.patch", "1202")?;
+    let part1 = solve(&alarm, &input).context("failed to find a solution")?;
     println!("part1: {}", part1);
 
-    let val = 19_690_720;
-    for noun in 0..100 {
-        for verb in 0..100 {
-            let result = solve(noun, verb, &input);
-            if result == Some(val) {
-                println!("part2: {}", 100 * noun + verb);
-                return Ok(());
-            }
-        }
+    let unknowns = [
+        Unknown {
+            address: 1,
+            range: 0..100,
+        },
+        Unknown {
+            address: 2,
+            range: 0..100,
+        },
+    ];
+    let solution = symbolic::solve(&input, &unknowns, Observe::Memory(0), 19_690_720)
+        .context("failed to find a noun and verb")?;
+    if let Method::BruteForce(reason) = &solution.method {
+        eprintln!("fell back to brute force: {}", reason);
     }
+    println!("part2: {}", 100 * solution.values[0] + solution.values[1]);
 
     Ok(())
 }
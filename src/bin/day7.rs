@@ -1,263 +1,77 @@
 #[macro_use]
 extern crate anyhow;
 
-use std::convert::TryInto;
-use std::fs;
-
 use anyhow::{Context, Result};
 
 use permutohedron::Heap;
 
-#[derive(Debug, PartialEq, Eq)]
-enum Parameter {
-    PositionMode(i64),
-    ImmediateMode(i64),
-}
+use advent_of_code_2019::intcode::network::{Network, Outcome};
+use advent_of_code_2019::intcode::search::{budget, search, Objective, Stats};
+use advent_of_code_2019::intcode::{read_program, run_program_with_limits};
 
-#[derive(Debug, PartialEq, Eq)]
-struct OpCodeMode {
-    opcode: u8,
-    p1: u8,
-    p2: u8,
-    p3: u8,
-}
-
-impl From<i64> for OpCodeMode {
-    fn from(code: i64) -> OpCodeMode {
-        OpCodeMode {
-            opcode: (code % 100) as u8,
-            p1: ((code / 100) % 10) as u8,
-            p2: ((code / 1000) % 10) as u8,
-            p3: ((code / 10000) % 10) as u8,
-        }
-    }
-}
-
-impl Parameter {
-    fn build(mode: u8, parameter: i64) -> Result<Parameter> {
-        match mode {
-            0 => Ok(Parameter::PositionMode(parameter)),
-            1 => Ok(Parameter::ImmediateMode(parameter)),
-            _ => Err(anyhow!("unknown mode {}", mode)),
-        }
-    }
-    fn realize(self, memory: &[i64]) -> Result<i64> {
-        match self {
-            Parameter::PositionMode(n) => {
-                let u: usize = n.try_into()?;
-                let out: &i64 = memory
-                    .get(u)
-                    .context(format!("failed to load memory address {}", n))?;
-                Ok(*out)
-            }
-            Parameter::ImmediateMode(n) => Ok(n),
-        }
-    }
-}
+const AMPLIFIERS: [&str; 5] = ["A", "B", "C", "D", "E"];
 
 fn read_input() -> Result<Vec<i64>> {
-    let contents = fs::read_to_string("input/day7.txt")?;
-    contents
-        .split(',')
-        .map(|s| Ok(s.trim().parse::<i64>()?))
-        .collect()
+    read_program("input/day7.txt")
 }
 
-struct CPU {
-    mem: Vec<i64>,
-    pc: usize,
-    last_output: Option<i64>,
-    inputs: Vec<i64>,
+fn process_phase(program: &[i64], phase: &[i64]) -> Result<i64> {
+    (0..5).try_fold(0, |signal, index| {
+        run_program_with_limits(program, &[phase[index], signal], budget())
+    })
 }
 
-enum Status<T> {
-    Ready(T),
-    Halted(T),
+/// Every ordering of `phases`.
+fn permutations(mut phases: [i64; 5]) -> Vec<[i64; 5]> {
+    Heap::new(&mut phases).collect()
 }
 
-impl CPU {
-    fn new(memory: &[i64]) -> CPU {
-        CPU {
-            mem: memory.to_vec(),
-            pc: 0,
-            last_output: None,
-            inputs: Vec::new(),
-        }
-    }
-
-    fn add_input(&mut self, input: i64) {
-        self.inputs.push(input)
-    }
-    fn step(&mut self) -> Result<Status<i64>> {
-        loop {
-            let code = self.mem.get(self.pc).context("read failed")?;
-            let modes: OpCodeMode = (*code).into();
-
-            let raw1 = self.mem.get(self.pc + 1).copied();
-            let raw2 = self.mem.get(self.pc + 2).copied();
-            let raw3 = self.mem.get(self.pc + 3).copied();
-
-            let p1 = raw1.context("invalid program p1");
-            let _p2 = raw2.context("invalid program p2");
-            let p3 = raw3.context("invalid program p3");
-
-            let real_p1 = raw1
-                .context("invalid program realp1")
-                .and_then(|i| Parameter::build(modes.p1, i)?.realize(&self.mem));
-            let real_p2 = raw2
-                .context("invalid program realp2")
-                .and_then(|i| Parameter::build(modes.p2, i)?.realize(&self.mem));
-            let _real_p3 = raw3
-                .context("invalid program realp3")
-                .and_then(|i| Parameter::build(modes.p3, i)?.realize(&self.mem));
-
-            match modes.opcode {
-                1 => {
-                    self.pc += 4;
-                    let output_addr: usize = p3?.try_into()?;
-                    self.mem[output_addr] = real_p1? + real_p2?;
-                }
-
-                2 => {
-                    self.pc += 4;
-                    let output_addr: usize = p3?.try_into()?;
-                    self.mem[output_addr] = real_p1? * real_p2?;
-                }
-
-                3 => {
-                    self.pc += 2;
-                    let addr: usize = p1?.try_into()?;
-                    let inp: &i64 = self.inputs.first().context("ran out of inputs")?;
-                    self.mem[addr] = *inp;
-                    let rest = self.inputs.iter().skip(1).copied().collect::<Vec<i64>>();
-                    self.inputs = rest;
-                    //let inp = self.inputs.pop().context("ran out of inputs")?;
-                }
-
-                4 => {
-                    self.pc += 2;
-                    let val = real_p1?;
-                    self.last_output = Some(val);
-                    return Ok(Status::Ready(val));
-                }
-                5 => {
-                    self.pc += 3;
-                    if real_p1? != 0 {
-                        self.pc = real_p2?.try_into()?;
-                    }
-                }
-                6 => {
-                    self.pc += 3;
-                    if real_p1? == 0 {
-                        self.pc = real_p2?.try_into()?;
-                    }
-                }
-                7 => {
-                    self.pc += 4;
-
-                    let output_addr: usize = p3?.try_into()?;
-                    self.mem[output_addr] = if real_p1? < real_p2? { 1 } else { 0 };
-                }
-
-                8 => {
-                    self.pc += 4;
-
-                    let output_addr: usize = p3?.try_into()?;
-                    self.mem[output_addr] = if real_p1? == real_p2? { 1 } else { 0 };
-                }
-                99 => break,
-                _ => {
-                    return Err(anyhow!("unknown opcode {}", modes.opcode));
-                }
-            };
-        }
-
-        Ok(Status::Halted(self.last_output.context("No output")?))
-    }
-}
-
-fn process_phase(program: &[i64], phase: &[i64]) -> Result<i64> {
-    let prog = program.to_vec();
-    (0..5).fold(Ok(0), |input_signal, index| match input_signal {
-        Ok(signal) => {
-            let input = vec![phase[index], signal];
-            let output = run_program(&prog, &input)?;
-            Ok(output)
-        }
-        Err(_) => input_signal,
-    })
+/// The biggest signal `run` gets out of any ordering of `phases`.
+fn max_power<F>(program: &[i64], phases: [i64; 5], run: F) -> Result<(i64, Stats)>
+where
+    F: Fn(&[i64], &[i64; 5]) -> Result<i64> + Sync,
+{
+    let found = search(&permutations(phases), program, Objective::Maximize, run);
+    let (_, power) = found.best.context("failed to get max power")?;
+    Ok((power, found.stats))
 }
 
 fn find_biggest_phase(program: &[i64]) -> Result<i64> {
-    let mut data = [0, 1, 2, 3, 4];
-    let mut heap = Heap::new(&mut data);
-
-    let mut max_power = None;
-    while let Some(phase) = heap.next_permutation() {
-        let power = process_phase(&program, phase)?;
-
-        if let Some(max) = max_power {
-            if max < power {
-                max_power = Some(power);
-            }
-        } else {
-            max_power = Some(power);
-        }
+    let (power, stats) = max_power(program, [0, 1, 2, 3, 4], |program, phase| {
+        process_phase(program, phase)
+    })?;
+    eprintln!("{}", stats);
+    Ok(power)
+}
+
+fn feedback_loop(program: &[i64], phase: &[i64]) -> Result<i64> {
+    let mut network = Network::new();
+    network.set_limits(budget());
+    for (name, phase) in AMPLIFIERS.iter().zip(phase.iter()) {
+        network.add_machine(name, program)?;
+        network.push(name, *phase);
     }
-    max_power.context("failed to get max power")
-}
+    network.wire("A -> B -> C -> D -> E -> A, E -> thrusters")?;
 
-/// Helper function for running a oneshot program on a CPU.
-fn run_program(memory: &[i64], input: &[i64]) -> Result<i64> {
-    let mut cpu = CPU::new(memory);
-    cpu.inputs = input.to_vec();
-    match cpu.step()? {
-        Status::Ready(out) => Ok(out),
-        Status::Halted(out) => Ok(out),
+    // Start at 0.
+    network.push("A", 0);
+    if network.run()? != Outcome::Halted {
+        return Err(anyhow!("amplifiers stalled"));
     }
+
+    network
+        .queue("thrusters")
+        .and_then(|q| q.back())
+        .copied()
+        .context("amplifiers halted without output")
 }
 
 fn part2(program: &[i64]) -> Result<i64> {
-    let mut data = [5, 6, 7, 8, 9];
-    let mut heap = Heap::new(&mut data);
-
-    let mut max_power = 0;
-    while let Some(phase) = heap.next_permutation() {
-        // Create 5 CPUs with the phase input as the first input.
-        let mut cpus: Vec<CPU> = phase
-            .iter()
-            .map(|phase| {
-                let mut cpu = CPU::new(program);
-                cpu.inputs = vec![*phase];
-                cpu
-            })
-            .collect();
-
-        // Start at 0.
-        cpus[0].add_input(0);
-
-        let mut power;
-        let mut index = 0;
-        loop {
-            match cpus[index].step()? {
-                Status::Ready(output) => {
-                    power = output;
-                }
-                Status::Halted(output) => {
-                    power = output;
-                    if index == 4 {
-                        break;
-                    }
-                }
-            }
-            index = (index + 1) % 5;
-            cpus[index].add_input(power);
-        }
-
-        max_power = std::cmp::max(max_power, power);
-    }
-
-    Ok(max_power)
+    let (power, stats) = max_power(program, [5, 6, 7, 8, 9], |program, phase| {
+        feedback_loop(program, phase)
+    })?;
+    eprintln!("{}", stats);
+    Ok(power)
 }
 
 fn main() -> Result<()> {
@@ -331,104 +145,3 @@ fn test_find_biggest_phase() -> Result<()> {
 
     Ok(())
 }
-
-#[test]
-fn test_run_program() -> Result<()> {
-    // Program test input == 8
-    let mut program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
-    let mut result = run_program(&mut program, &vec![8])?;
-    assert_eq!(1, result);
-
-    program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
-    result = run_program(&mut program, &vec![3])?;
-    assert_eq!(0, result);
-
-    // Program test input < 8
-    program = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
-    result = run_program(&mut program, &vec![4])?;
-    assert_eq!(1, result);
-
-    program = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
-    result = run_program(&mut program, &vec![99])?;
-    assert_eq!(0, result);
-
-    // Program test input == 8 immediate mode
-    program = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
-    result = run_program(&mut program, &vec![8])?;
-    assert_eq!(1, result);
-
-    program = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
-    result = run_program(&mut program, &vec![10])?;
-    assert_eq!(0, result);
-
-    // Program test input < 8 immediate mode
-    program = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
-    result = run_program(&mut program, &vec![4])?;
-    assert_eq!(1, result);
-
-    program = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
-    result = run_program(&mut program, &vec![99])?;
-    assert_eq!(0, result);
-
-    // Jump tests for zero.
-    program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
-    result = run_program(&mut program, &vec![0])?;
-    assert_eq!(0, result);
-
-    program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
-    result = run_program(&mut program, &vec![-1])?;
-    assert_eq!(1, result);
-
-    // Jump tests for zero immediate mode
-    program = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
-    result = run_program(&mut program, &vec![0])?;
-    assert_eq!(0, result);
-
-    program = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
-    result = run_program(&mut program, &vec![-1])?;
-    assert_eq!(1, result);
-
-    // Long example
-    let long = vec![
-        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
-        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
-        1105, 1, 46, 98, 99,
-    ];
-
-    let mut a = long.clone();
-    result = run_program(&mut a, &vec![7])?;
-    assert_eq!(999, result);
-
-    let mut b = long.clone();
-    result = run_program(&mut b, &vec![9])?;
-    assert_eq!(1001, result);
-
-    let mut c = long.clone();
-    result = run_program(&mut c, &vec![8])?;
-    assert_eq!(1000, result);
-
-    Ok(())
-}
-
-#[test]
-fn from_opcode_mode() {
-    assert_eq!(
-        OpCodeMode {
-            opcode: 2,
-            p1: 0,
-            p2: 1,
-            p3: 0
-        },
-        1002.into()
-    );
-
-    assert_eq!(
-        OpCodeMode {
-            opcode: 99,
-            p1: 0,
-            p2: 0,
-            p3: 1
-        },
-        10099.into()
-    );
-}
@@ -0,0 +1,225 @@
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+
+use advent_of_code_2019::intcode::debugger::{Debugger, Stop};
+use advent_of_code_2019::intcode::disasm::decode;
+use advent_of_code_2019::intcode::snapshot::Snapshot;
+use advent_of_code_2019::intcode::{read_program, CPU};
+
+const HELP: &str = "\
+commands:
+  s, step [n]         execute n instructions (default 1)
+  c, continue         run until a breakpoint, watchpoint, output or halt
+  bs, back [n]        undo n instructions (default 1)
+  rewind <count>      rewind to when count instructions had been executed
+  last <addr>         rewind to just before the last write of a memory address
+  b, break <pc>       toggle a breakpoint
+  w, watch <addr>     toggle a watchpoint on a memory address
+  i, input <v>...     queue input values
+  r, regs             print pc, relative base, instruction count and pending input
+  m, mem <addr> [n]   print n words of memory (default 8)
+  l, list [pc] [n]    disassemble n instructions (default 5)
+  o, outputs          print everything output so far
+  dump <file>         save the machine state to a file
+  load <file>         restore the machine state from a file, clearing outputs,
+                      breakpoints and watchpoints
+  q, quit";
+
+/// Parse an address or count, which are never negative.
+fn parse_number<T>(arg: Option<&&str>, default: Option<T>) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match arg {
+        Some(s) => s.parse().with_context(|| format!("invalid number {}", s)),
+        None => default.context("missing argument"),
+    }
+}
+
+fn list(debugger: &Debugger, pc: usize, count: usize) {
+    let memory = debugger.cpu().memory();
+    let mut address = pc;
+    for _ in 0..count {
+        if address >= memory.len() {
+            break;
+        }
+        let marker = if address == debugger.cpu().pc() {
+            "=>"
+        } else {
+            "  "
+        };
+        match decode(&memory.slice(address, 4), 0) {
+            Some(line) => {
+                println!("{} {:>5}: {}", marker, address, line);
+                address += line.len();
+            }
+            None => {
+                println!(
+                    "{} {:>5}: DB {}",
+                    marker,
+                    address,
+                    debugger.cpu().get_memory(address)
+                );
+                address += 1;
+            }
+        }
+    }
+}
+
+fn report(debugger: &Debugger, stop: Stop) {
+    match stop {
+        Stop::Stepped => {}
+        Stop::Breakpoint(pc) => println!("breakpoint at {}", pc),
+        Stop::Watchpoint { address, old, new } => {
+            println!("watchpoint [{}]: {} -> {}", address, old, new)
+        }
+        Stop::Output(value) => println!("output: {}", value),
+        Stop::NeedsInput => println!("waiting for input"),
+        Stop::Halted => println!("halted"),
+    }
+    list(debugger, debugger.cpu().pc(), 1);
+}
+
+fn command(debugger: &mut Debugger, line: &str) -> Result<bool> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let args = &words[1..];
+    match words[0] {
+        "s" | "step" => {
+            let count: u64 = parse_number(args.first(), Some(1))?;
+            let mut stop = Stop::Stepped;
+            for _ in 0..count {
+                stop = debugger.step()?;
+                if stop != Stop::Stepped {
+                    break;
+                }
+            }
+            report(debugger, stop);
+        }
+        "c" | "continue" => {
+            let stop = debugger.resume()?;
+            report(debugger, stop);
+        }
+        "bs" | "back" => {
+            let count: u64 = parse_number(args.first(), Some(1))?;
+            let count = debugger.instructions().saturating_sub(count);
+            debugger.rewind(count)?;
+            list(debugger, debugger.cpu().pc(), 1);
+        }
+        "rewind" => {
+            let count: u64 = parse_number(args.first(), None)?;
+            debugger.rewind(count)?;
+            list(debugger, debugger.cpu().pc(), 1);
+        }
+        "last" => {
+            let address: usize = parse_number(args.first(), None)?;
+            match debugger.rewind_to_write(address) {
+                Some(count) => {
+                    println!("last write to [{}] after {} instructions", address, count);
+                    list(debugger, debugger.cpu().pc(), 1);
+                }
+                None => println!("no recorded write to [{}]", address),
+            }
+        }
+        "b" | "break" => {
+            let pc: usize = parse_number(args.first(), None)?;
+            let set = debugger.toggle_breakpoint(pc);
+            println!("breakpoint {} {}", pc, if set { "set" } else { "cleared" });
+        }
+        "w" | "watch" => {
+            let address: usize = parse_number(args.first(), None)?;
+            let set = debugger.toggle_watchpoint(address);
+            println!(
+                "watchpoint {} {}",
+                address,
+                if set { "set" } else { "cleared" }
+            );
+        }
+        "i" | "input" => {
+            for arg in args {
+                debugger.cpu_mut().add_input(arg.parse()?);
+            }
+        }
+        "r" | "regs" => {
+            let cpu = debugger.cpu();
+            println!("pc: {}", cpu.pc());
+            println!("rb: {}", cpu.relative_base());
+            println!("instructions: {}", debugger.instructions());
+            println!("input: {:?}", cpu.inputs());
+            println!(
+                "memory: {} words, {} bytes",
+                cpu.memory().len(),
+                cpu.memory().bytes()
+            );
+        }
+        "m" | "mem" => {
+            let start: usize = parse_number(args.first(), None)?;
+            let count: usize = parse_number(args.get(1), Some(8))?;
+            for address in start..start.saturating_add(count) {
+                println!("[{}] = {}", address, debugger.cpu().get_memory(address));
+            }
+        }
+        "l" | "list" => {
+            let pc = parse_number(args.first(), Some(debugger.cpu().pc()))?;
+            let count: usize = parse_number(args.get(1), Some(5))?;
+            list(debugger, pc, count);
+        }
+        "o" | "outputs" => println!("{:?}", debugger.outputs()),
+        "dump" => {
+            let path = args.first().context("missing file")?;
+            debugger.cpu().snapshot().save(path)?;
+        }
+        "load" => {
+            let path = args.first().context("missing file")?;
+            debugger.load(&Snapshot::load(path)?);
+            list(debugger, debugger.cpu().pc(), 1);
+        }
+        "q" | "quit" => return Ok(false),
+        _ => println!("{}", HELP),
+    }
+    Ok(true)
+}
+
+/// Debug an Intcode program, e.g. `cargo run --bin debugger input/day9.txt 1`.
+///
+/// A `.json` dump written by the `dump` command can be given instead of a program.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .context("usage: debugger <program> [inputs...]")?;
+    let mut debugger = if path.ends_with(".json") {
+        Debugger::from_cpu(CPU::from_snapshot(&Snapshot::load(&path)?))
+    } else {
+        Debugger::new(&read_program(&path)?)
+    };
+    for arg in args {
+        debugger.cpu_mut().add_input(arg.parse()?);
+    }
+
+    list(&debugger, debugger.cpu().pc(), 1);
+    let stdin = io::stdin();
+    loop {
+        print!("(intcode) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match command(&mut debugger, &line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => println!("error: {}", e),
+        }
+    }
+
+    Ok(())
+}
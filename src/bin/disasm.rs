@@ -0,0 +1,31 @@
+#[macro_use]
+extern crate anyhow;
+
+use std::env;
+
+use anyhow::{Context, Result};
+
+use advent_of_code_2019::intcode::analysis::analyze;
+use advent_of_code_2019::intcode::decompile::decompile;
+use advent_of_code_2019::intcode::disasm::listing;
+use advent_of_code_2019::intcode::read_program;
+
+/// Print a listing of an Intcode program, e.g. `cargo run --bin disasm input/day9.txt`.
+///
+/// With `--dot` print its control-flow graph for Graphviz instead, with `--decompile`
+/// its pseudo-code.
+fn main() -> Result<()> {
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let path = args
+        .first()
+        .context("usage: disasm [--dot | --decompile] <program>")?;
+    let program = read_program(path)?;
+    match flags.first().map(|flag| flag.as_str()) {
+        None => print!("{}", listing(&program)),
+        Some("--dot") => print!("{}", analyze(&program).to_dot()),
+        Some("--decompile") => print!("{}", decompile(&program)),
+        Some(flag) => return Err(anyhow!("unknown flag {}", flag)),
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::cell::RefCell;
+use std::env;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+
+use advent_of_code_2019::intcode::error::IntcodeError;
+use advent_of_code_2019::intcode::trace::Profiler;
+use advent_of_code_2019::intcode::{read_program, CPU};
+
+/// Run an Intcode program to completion, or until it runs out of input, and print
+/// where it spent its time, e.g. `cargo run --release --bin profile input/day9.txt 2`.
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let path = args
+        .next()
+        .context("usage: profile <program> [inputs...]")?;
+    let program = read_program(&path)?;
+
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    let mut cpu = CPU::new(&program);
+    cpu.set_tracer(Box::new(profiler.clone()));
+    for arg in args {
+        cpu.add_input(arg.parse()?);
+    }
+
+    let mut error = None;
+    for out in cpu.outputs() {
+        match out {
+            Ok(value) => println!("output: {}", value),
+            Err(IntcodeError::InputExhausted { at }) => {
+                println!("stopped waiting for input at {}", at)
+            }
+            Err(e) => error = Some(e),
+        }
+    }
+
+    // Whatever was collected is still worth seeing when the program fails.
+    println!();
+    print!("{}", profiler.borrow().report(&program, 20));
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
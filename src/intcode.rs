@@ -0,0 +1,1081 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fs;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+pub mod analysis;
+pub mod ascii;
+pub mod asm;
+pub mod debugger;
+pub mod decompile;
+pub mod device;
+pub mod disasm;
+pub mod error;
+pub mod fast;
+pub mod history;
+pub mod isa;
+pub mod limits;
+pub mod memory;
+pub mod network;
+pub mod op;
+pub mod patch;
+pub mod search;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+
+use device::{InputDevice, OutputDevice};
+use error::{IntcodeError, Location};
+use history::Undo;
+use isa::{Effect, Instruction, InstructionSet};
+use limits::{Limits, Usage};
+use memory::Memory;
+use op::Op;
+use snapshot::Snapshot;
+use trace::{Trace, Tracer};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Parameter {
+    PositionMode(i64),
+    ImmediateMode(i64),
+    RelativeMode(i64),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct OpCodeMode {
+    pub opcode: u8,
+    pub p1: u8,
+    pub p2: u8,
+    pub p3: u8,
+}
+
+impl From<i64> for OpCodeMode {
+    fn from(code: i64) -> OpCodeMode {
+        OpCodeMode {
+            opcode: (code % 100) as u8,
+            p1: ((code / 100) % 10) as u8,
+            p2: ((code / 1000) % 10) as u8,
+            p3: ((code / 10000) % 10) as u8,
+        }
+    }
+}
+
+/// Convert a value from the program into an address, refusing negative ones.
+pub fn to_address(address: i64, at: Location) -> Result<usize, IntcodeError> {
+    usize::try_from(address).map_err(|_| IntcodeError::NegativeAddress { address, at })
+}
+
+/// Offset the relative base, failing rather than wrapping around.
+fn relative(relative_base: i64, offset: i64, at: Location) -> Result<i64, IntcodeError> {
+    relative_base
+        .checked_add(offset)
+        .ok_or(IntcodeError::Overflow { at })
+}
+
+impl Parameter {
+    pub fn build(mode: u8, parameter: i64) -> Option<Parameter> {
+        match mode {
+            0 => Some(Parameter::PositionMode(parameter)),
+            1 => Some(Parameter::ImmediateMode(parameter)),
+            2 => Some(Parameter::RelativeMode(parameter)),
+            _ => None,
+        }
+    }
+
+    pub fn realize(
+        self,
+        memory: &Memory,
+        relative_base: i64,
+        at: Location,
+    ) -> Result<i64, IntcodeError> {
+        match self {
+            Parameter::PositionMode(n) => Ok(memory.get(to_address(n, at)?).unwrap_or(0)),
+            Parameter::ImmediateMode(n) => Ok(n),
+            Parameter::RelativeMode(n) => {
+                let address = to_address(relative(relative_base, n, at)?, at)?;
+                Ok(memory.get(address).unwrap_or(0))
+            }
+        }
+    }
+
+    /// The address a write parameter points at. Immediate mode is treated like position
+    /// mode unless `strict` is set, in which case it's an error.
+    pub fn realize_write(
+        self,
+        relative_base: i64,
+        strict: bool,
+        at: Location,
+    ) -> Result<usize, IntcodeError> {
+        match self {
+            Parameter::ImmediateMode(_) if strict => Err(IntcodeError::ImmediateWrite { at }),
+            Parameter::ImmediateMode(n) | Parameter::PositionMode(n) => to_address(n, at),
+            Parameter::RelativeMode(n) => to_address(relative(relative_base, n, at)?, at),
+        }
+    }
+}
+
+/// Read a comma separated Intcode program from disk.
+pub fn read_program(path: &str) -> Result<Vec<i64>> {
+    let contents = fs::read_to_string(path)?;
+    contents
+        .split(',')
+        .map(|s| Ok(s.trim().parse::<i64>()?))
+        .collect()
+}
+
+pub struct CPU {
+    mem: Memory,
+    pc: usize,
+    inputs: VecDeque<i64>,
+    input: Option<Box<dyn InputDevice>>,
+    output: Option<Box<dyn OutputDevice>>,
+    relative_base: i64,
+    strict: bool,
+    isa: Option<Rc<dyn InstructionSet>>,
+    exit_code: Option<i64>,
+    limits: Limits,
+    usage: Usage,
+    tracer: Option<Box<dyn Tracer>>,
+    history: Option<VecDeque<Undo>>,
+    history_limit: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Status<T> {
+    Ready(T),
+    /// The program wants to read but no input is queued. The program counter is
+    /// left on the input instruction so it can be resumed after `add_input`.
+    NeedsInput,
+    /// The program hit opcode 99.
+    Halted,
+}
+
+impl CPU {
+    pub fn new(memory: &[i64]) -> CPU {
+        CPU {
+            mem: Memory::new(memory),
+            pc: 0,
+            inputs: VecDeque::new(),
+            input: None,
+            output: None,
+            relative_base: 0,
+            strict: false,
+            isa: None,
+            exit_code: None,
+            limits: Limits::default(),
+            usage: Usage::default(),
+            tracer: None,
+            history: None,
+            history_limit: history::DEFAULT_LIMIT,
+        }
+    }
+
+    /// Report every executed instruction to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    /// Read input from `device` whenever nothing is queued with `add_input`.
+    pub fn set_input(&mut self, device: Box<dyn InputDevice>) {
+        self.input = Some(device);
+    }
+
+    pub fn take_input(&mut self) -> Option<Box<dyn InputDevice>> {
+        self.input.take()
+    }
+
+    /// Send output to `device` instead of returning it from `step` as `Status::Ready`.
+    pub fn set_output(&mut self, device: Box<dyn OutputDevice>) {
+        self.output = Some(device);
+    }
+
+    pub fn take_output(&mut self) -> Option<Box<dyn OutputDevice>> {
+        self.output.take()
+    }
+
+    fn next_input(&mut self) -> Option<i64> {
+        self.inputs
+            .pop_front()
+            .or_else(|| self.input.as_mut().and_then(|device| device.read()))
+    }
+
+    /// In strict mode writing through an immediate mode parameter fails with
+    /// `IntcodeError::ImmediateWrite`, otherwise it writes to the address like
+    /// position mode does. Lenient by default.
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Run programs with a different instruction set, the standard one is `isa::Standard`.
+    pub fn set_instruction_set(&mut self, isa: Rc<dyn InstructionSet>) {
+        self.isa = Some(isa);
+    }
+
+    /// The code a custom instruction halted the program with, see `isa::Effect::Exit`.
+    pub fn exit_code(&self) -> Option<i64> {
+        self.exit_code
+    }
+
+    /// Fail with `IntcodeError::LimitExceeded` rather than go over `limits`. Usage
+    /// counts from when the machine was created or restored, not from when limits
+    /// were set.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Instructions executed and values output so far.
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Keep an undo log of executed instructions from now on, so they can be taken
+    /// back with `undo`. Turning it off drops the log.
+    pub fn record_history(&mut self, on: bool) {
+        if on != self.history.is_some() {
+            self.history = if on { Some(VecDeque::new()) } else { None };
+        }
+    }
+
+    /// Only keep the last `limit` instructions in the undo log,
+    /// `history::DEFAULT_LIMIT` by default.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        if let Some(history) = self.history.as_mut() {
+            while history.len() > limit {
+                history.pop_front();
+            }
+        }
+    }
+
+    /// The undo log, oldest instruction first.
+    pub fn history(&self) -> &VecDeque<Undo> {
+        const EMPTY: &VecDeque<Undo> = &VecDeque::new();
+        self.history.as_ref().unwrap_or(EMPTY)
+    }
+
+    /// Take back the last recorded instruction, restoring memory, the program counter,
+    /// the relative base and any input it consumed.
+    pub fn undo(&mut self) -> Option<Undo> {
+        let undo = self.history.as_mut()?.pop_back()?;
+        if let Some(write) = undo.write {
+            self.mem.set(write.address, write.old);
+            self.mem.truncate(write.len);
+        }
+        if let Some(input) = undo.input {
+            self.inputs.push_front(input);
+        }
+        self.pc = undo.pc;
+        self.relative_base = undo.relative_base;
+        self.exit_code = None;
+        self.usage.instructions -= 1;
+        if undo.output.is_some() {
+            self.usage.outputs -= 1;
+        }
+        Some(undo)
+    }
+
+    /// Capture the full machine state.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            len: self.mem.len(),
+            mem: self.mem.chunks(),
+            pc: self.pc,
+            relative_base: self.relative_base,
+            inputs: self.inputs.iter().copied().collect(),
+        }
+    }
+
+    /// Rewind the machine to a snapshot. Any tracer stays attached, the undo log is
+    /// cleared, and usage and the exit code start over as on a new machine.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.exit_code = None;
+        self.usage = Usage::default();
+        self.mem = Memory::from_chunks(snapshot.len, &snapshot.mem);
+        self.pc = snapshot.pc;
+        self.relative_base = snapshot.relative_base;
+        self.inputs = snapshot.inputs.iter().copied().collect();
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> CPU {
+        let mut cpu = CPU::new(&[]);
+        cpu.restore(snapshot);
+        cpu
+    }
+
+    pub fn add_input(&mut self, input: i64) {
+        self.inputs.push_back(input)
+    }
+
+    pub fn get_memory(&self, position: usize) -> i64 {
+        self.mem.get(position).unwrap_or(0)
+    }
+
+    pub fn set_memory(&mut self, position: usize, value: i64) {
+        self.mem.set(position, value);
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.mem
+    }
+
+    /// Branch off a copy of this machine. Memory is shared copy-on-write, so this is
+    /// cheap even for big programs. Limits and usage are copied, the tracer and undo
+    /// log are not carried over.
+    pub fn fork(&self) -> CPU {
+        CPU {
+            mem: self.mem.clone(),
+            pc: self.pc,
+            inputs: self.inputs.clone(),
+            input: None,
+            output: None,
+            relative_base: self.relative_base,
+            strict: self.strict,
+            isa: self.isa.clone(),
+            exit_code: self.exit_code,
+            limits: self.limits,
+            usage: self.usage,
+            tracer: None,
+            history: None,
+            history_limit: self.history_limit,
+        }
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// The current program counter, instruction and relative base, for error reporting.
+    pub fn location(&self) -> Location {
+        Location {
+            pc: self.pc,
+            instruction: self.mem.get(self.pc).unwrap_or(0),
+            relative_base: self.relative_base,
+        }
+    }
+
+    /// Inputs queued up but not yet read.
+    pub fn inputs(&self) -> &VecDeque<i64> {
+        &self.inputs
+    }
+
+    /// Iterate over the values the program outputs until it halts.
+    ///
+    /// If the program runs out of input the iterator yields `InputExhausted` and
+    /// stops, leaving the machine on the input instruction so it can be resumed.
+    pub fn outputs(&mut self) -> Outputs<'_> {
+        Outputs {
+            cpu: self,
+            done: false,
+        }
+    }
+
+    /// Run the program until it halts, returning everything it output.
+    pub fn run_to_completion(&mut self) -> Result<Vec<i64>, IntcodeError> {
+        self.outputs().collect()
+    }
+
+    /// Run until the next output, until input is needed or until the program halts.
+    pub fn step(&mut self) -> Result<Status<i64>, IntcodeError> {
+        loop {
+            if let Some(status) = self.execute()? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Decode the parameter `offset` words after the program counter.
+    fn parameter(&self, offset: usize, mode: u8, at: Location) -> Result<Parameter, IntcodeError> {
+        if self.isa.as_ref().is_some_and(|isa| !isa.has_mode(mode)) {
+            return Err(IntcodeError::BadParameterMode { mode, at });
+        }
+        let raw = self
+            .mem
+            .get(self.pc + offset)
+            .ok_or(IntcodeError::PcOutOfBounds { at })?;
+        Parameter::build(mode, raw).ok_or(IntcodeError::BadParameterMode { mode, at })
+    }
+
+    /// The value of the parameter `offset` words after the program counter.
+    fn read(&self, offset: usize, mode: u8, at: Location) -> Result<i64, IntcodeError> {
+        match &self.isa {
+            Some(isa) if mode > 2 && isa.has_mode(mode) => {
+                let raw = self
+                    .mem
+                    .get(self.pc + offset)
+                    .ok_or(IntcodeError::PcOutOfBounds { at })?;
+                isa.read_mode(mode, raw, &self.mem, self.relative_base)
+                    .ok_or(IntcodeError::BadParameterMode { mode, at })
+            }
+            _ => self
+                .parameter(offset, mode, at)?
+                .realize(&self.mem, self.relative_base, at),
+        }
+    }
+
+    fn emit(&mut self, value: i64) -> Result<Option<Status<i64>>, IntcodeError> {
+        if let Some(limit) = self.limits.check_output(&self.usage) {
+            return Err(IntcodeError::LimitExceeded {
+                limit,
+                at: self.location(),
+            });
+        }
+        self.usage.outputs += 1;
+        match self.output.as_mut() {
+            Some(device) => {
+                device.write(value);
+                Ok(None)
+            }
+            None => Ok(Some(Status::Ready(value))),
+        }
+    }
+
+    fn address(&self, value: i64) -> Result<usize, IntcodeError> {
+        usize::try_from(value).map_err(|_| IntcodeError::NegativeAddress {
+            address: value,
+            at: self.location(),
+        })
+    }
+
+    fn relative_address(&self, offset: i64) -> Result<usize, IntcodeError> {
+        match self.relative_base.checked_add(offset) {
+            Some(address) => self.address(address),
+            None => Err(IntcodeError::Overflow {
+                at: self.location(),
+            }),
+        }
+    }
+
+    fn raw(&self, offset: usize) -> Result<i64, IntcodeError> {
+        self.mem
+            .get(self.pc + offset)
+            .ok_or_else(|| IntcodeError::PcOutOfBounds {
+                at: self.location(),
+            })
+    }
+
+    /// `read` for the standard modes.
+    fn operand(&self, offset: usize, mode: u8) -> Result<i64, IntcodeError> {
+        let raw = self.raw(offset)?;
+        let address = match mode {
+            0 => self.address(raw)?,
+            1 => return Ok(raw),
+            2 => self.relative_address(raw)?,
+            _ => {
+                return Err(IntcodeError::BadParameterMode {
+                    mode,
+                    at: self.location(),
+                })
+            }
+        };
+        Ok(self.mem.get(address).unwrap_or(0))
+    }
+
+    /// The address a standard write parameter points at.
+    fn target(&self, offset: usize, mode: u8) -> Result<usize, IntcodeError> {
+        let raw = self.raw(offset)?;
+        match mode {
+            0 => self.address(raw),
+            1 if self.strict => Err(IntcodeError::ImmediateWrite {
+                at: self.location(),
+            }),
+            1 => self.address(raw),
+            2 => self.relative_address(raw),
+            _ => Err(IntcodeError::BadParameterMode {
+                mode,
+                at: self.location(),
+            }),
+        }
+    }
+
+    /// `execute` for the standard instruction set when there are no limits to check and
+    /// nothing to trace or record, which is how most programs run. Errors work out
+    /// their location only when they happen.
+    fn execute_plain(&mut self) -> Result<Option<Status<i64>>, IntcodeError> {
+        let code = self.raw(0)?;
+        let modes: OpCodeMode = code.into();
+        let op = match Op::from_opcode(modes.opcode) {
+            Some(op) => op,
+            None => {
+                return Err(IntcodeError::UnknownOpcode {
+                    opcode: code % 100,
+                    at: self.location(),
+                })
+            }
+        };
+        let modes = [modes.p1, modes.p2, modes.p3];
+
+        let mut reads = [0; 2];
+        for (i, read) in reads.iter_mut().enumerate().take(op.reads()) {
+            *read = self.operand(i + 1, modes[i])?;
+        }
+        let [a, b] = reads;
+        let mut next = self.pc + 1 + op.arity();
+        let mut status = None;
+        match op {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => {
+                let target = self.target(3, modes[2])?;
+                let value = match op {
+                    Op::Add => a.checked_add(b),
+                    Op::Mul => a.checked_mul(b),
+                    Op::Lt => Some((a < b) as i64),
+                    _ => Some((a == b) as i64),
+                };
+                match value {
+                    Some(value) => self.mem.set(target, value),
+                    None => {
+                        return Err(IntcodeError::Overflow {
+                            at: self.location(),
+                        })
+                    }
+                }
+            }
+            Op::In => {
+                let target = self.target(1, modes[0])?;
+                match self.next_input() {
+                    Some(input) => self.mem.set(target, input),
+                    None => return Ok(Some(Status::NeedsInput)),
+                }
+            }
+            Op::Out => status = self.emit(a)?,
+            Op::Jnz | Op::Jz => {
+                if (a != 0) == (op == Op::Jnz) {
+                    next = self.address(b)?;
+                }
+            }
+            Op::Arb => {
+                self.relative_base = match self.relative_base.checked_add(a) {
+                    Some(base) => base,
+                    None => {
+                        return Err(IntcodeError::Overflow {
+                            at: self.location(),
+                        })
+                    }
+                }
+            }
+            Op::Hlt => {
+                self.usage.instructions += 1;
+                return Ok(Some(Status::Halted));
+            }
+        }
+        self.pc = next;
+        self.usage.instructions += 1;
+        Ok(status)
+    }
+
+    /// Execute a single instruction, returning a status if it produced output, needs
+    /// input or halted. The program counter doesn't move on the latter two.
+    ///
+    /// Only the parameters the opcode actually has are decoded, as given by `Op` or
+    /// the instruction set.
+    pub fn execute(&mut self) -> Result<Option<Status<i64>>, IntcodeError> {
+        if self.isa.is_none()
+            && self.tracer.is_none()
+            && self.history.is_none()
+            && self.limits.is_unlimited()
+        {
+            return self.execute_plain();
+        }
+        let code = self
+            .mem
+            .get(self.pc)
+            .ok_or_else(|| IntcodeError::PcOutOfBounds {
+                at: self.location(),
+            })?;
+        let at = Location {
+            pc: self.pc,
+            instruction: code,
+            relative_base: self.relative_base,
+        };
+        if let Some(limit) = self.limits.check_instruction(&self.usage) {
+            return Err(IntcodeError::LimitExceeded { limit, at });
+        }
+        let modes: OpCodeMode = code.into();
+        let opcode = modes.opcode;
+        let instruction = match &self.isa {
+            None => Op::from_opcode(opcode).map(Instruction::Standard),
+            Some(isa) => isa.instruction(opcode),
+        }
+        .ok_or(IntcodeError::UnknownOpcode {
+            opcode: code % 100,
+            at,
+        })?;
+        let modes = [modes.p1, modes.p2, modes.p3];
+
+        let mut reads = [0; 3];
+        for (i, read) in reads.iter_mut().enumerate().take(instruction.reads()) {
+            *read = self.read(i + 1, modes[i], at)?;
+        }
+        let arity = instruction.arity();
+        let target = if instruction.writes() {
+            let p = self.parameter(arity, modes[arity - 1], at)?;
+            let address = p.realize_write(self.relative_base, self.strict, at)?;
+            if let Some(limit) = self.limits.check_write(address) {
+                return Err(IntcodeError::LimitExceeded { limit, at });
+            }
+            Some(address)
+        } else {
+            None
+        };
+
+        let pc = self.pc;
+        let mut next = pc + 1 + arity;
+        let [a, b, _] = reads;
+        let relative_base = self.relative_base;
+        let mut value = None;
+        let mut input = None;
+        let mut output = None;
+        let mut status = None;
+        match instruction {
+            Instruction::Standard(op) => match op {
+                Op::Add => value = Some(a.checked_add(b).ok_or(IntcodeError::Overflow { at })?),
+                Op::Mul => value = Some(a.checked_mul(b).ok_or(IntcodeError::Overflow { at })?),
+                Op::Lt => value = Some((a < b) as i64),
+                Op::Eq => value = Some((a == b) as i64),
+                Op::In => match self.next_input() {
+                    Some(read) => {
+                        value = Some(read);
+                        input = Some(read);
+                    }
+                    None => return Ok(Some(Status::NeedsInput)),
+                },
+                Op::Out => {
+                    status = self.emit(a)?;
+                    output = Some(a);
+                }
+                Op::Jnz | Op::Jz => {
+                    if (a != 0) == (op == Op::Jnz) {
+                        next = to_address(b, at)?;
+                    }
+                }
+                Op::Arb => self.relative_base = relative(self.relative_base, a, at)?,
+                Op::Hlt => status = Some(Status::Halted),
+            },
+            Instruction::Custom { .. } => {
+                let isa = self.isa.clone().expect("custom instruction without an ISA");
+                match isa.execute(opcode, &reads[..instruction.reads()]) {
+                    Effect::Continue => {}
+                    Effect::Write(_) if target.is_none() => {
+                        return Err(IntcodeError::NoWriteParameter { at })
+                    }
+                    Effect::Write(v) => value = Some(v),
+                    Effect::Jump(address) => next = to_address(address, at)?,
+                    Effect::Output(v) => {
+                        status = self.emit(v)?;
+                        output = Some(v);
+                    }
+                    Effect::Halt => status = Some(Status::Halted),
+                    Effect::Exit(code) => {
+                        self.exit_code = Some(code);
+                        status = Some(Status::Halted);
+                    }
+                }
+            }
+        }
+
+        let write = target.zip(value);
+        if let Some(history) = self.history.as_mut() {
+            let mem = &self.mem;
+            history.push_back(Undo {
+                pc,
+                relative_base,
+                write: write.map(|(address, _)| history::Write {
+                    address,
+                    old: mem.get(address).unwrap_or(0),
+                    len: mem.len(),
+                }),
+                input,
+                output,
+            });
+            if history.len() > self.history_limit {
+                history.pop_front();
+            }
+        }
+        if let Some((address, value)) = write {
+            self.mem.set(address, value);
+        }
+        if status != Some(Status::Halted) {
+            self.pc = next;
+        }
+        self.usage.instructions += 1;
+
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.trace(&Trace {
+                pc,
+                opcode,
+                operands: reads[..instruction.reads()].to_vec(),
+                write,
+            });
+        }
+
+        Ok(status)
+    }
+}
+
+/// See `CPU::outputs`.
+pub struct Outputs<'a> {
+    cpu: &'a mut CPU,
+    done: bool,
+}
+
+impl<'a> Iterator for Outputs<'a> {
+    type Item = Result<i64, IntcodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = match self.cpu.step() {
+            Ok(Status::Ready(value)) => return Some(Ok(value)),
+            Ok(Status::Halted) => None,
+            Ok(Status::NeedsInput) => Some(Err(IntcodeError::InputExhausted {
+                at: self.cpu.location(),
+            })),
+            Err(e) => Some(Err(e)),
+        };
+        self.done = true;
+        item
+    }
+}
+
+/// Helper function for running a oneshot program on a CPU.
+pub fn run_program(memory: &[i64], input: &[i64]) -> Result<i64> {
+    run_program_with_limits(memory, input, Limits::new())
+}
+
+/// Like `run_program`, failing rather than go over `limits`.
+pub fn run_program_with_limits(memory: &[i64], input: &[i64], limits: Limits) -> Result<i64> {
+    let mut cpu = CPU::new(memory);
+    cpu.set_limits(limits);
+    for i in input {
+        cpu.add_input(*i);
+    }
+    match cpu.step()? {
+        Status::Ready(out) => Ok(out),
+        Status::NeedsInput => Err(IntcodeError::InputExhausted { at: cpu.location() }.into()),
+        Status::Halted => Err(anyhow!("no output")),
+    }
+}
+
+#[test]
+fn test_run_program() -> Result<()> {
+    // Program test input == 8
+    let mut program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+    let mut result = run_program(&program, &[8])?;
+    assert_eq!(1, result);
+
+    program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+    result = run_program(&program, &[3])?;
+    assert_eq!(0, result);
+
+    // Program test input < 8
+    program = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+    result = run_program(&program, &[4])?;
+    assert_eq!(1, result);
+
+    program = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+    result = run_program(&program, &[99])?;
+    assert_eq!(0, result);
+
+    // Program test input == 8 immediate mode
+    program = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
+    result = run_program(&program, &[8])?;
+    assert_eq!(1, result);
+
+    program = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
+    result = run_program(&program, &[10])?;
+    assert_eq!(0, result);
+
+    // Program test input < 8 immediate mode
+    program = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
+    result = run_program(&program, &[4])?;
+    assert_eq!(1, result);
+
+    program = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
+    result = run_program(&program, &[99])?;
+    assert_eq!(0, result);
+
+    // Jump tests for zero.
+    program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+    result = run_program(&program, &[0])?;
+    assert_eq!(0, result);
+
+    program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+    result = run_program(&program, &[-1])?;
+    assert_eq!(1, result);
+
+    // Jump tests for zero immediate mode
+    program = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+    result = run_program(&program, &[0])?;
+    assert_eq!(0, result);
+
+    program = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+    result = run_program(&program, &[-1])?;
+    assert_eq!(1, result);
+
+    // Long example
+    let long = vec![
+        3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
+        1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
+        1105, 1, 46, 98, 99,
+    ];
+
+    result = run_program(&long, &[7])?;
+    assert_eq!(999, result);
+
+    result = run_program(&long, &[9])?;
+    assert_eq!(1001, result);
+
+    result = run_program(&long, &[8])?;
+    assert_eq!(1000, result);
+
+    Ok(())
+}
+
+#[test]
+fn test_halt_without_output() -> Result<()> {
+    let mut cpu = CPU::new(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+    assert_eq!(Status::Halted, cpu.step()?);
+    assert_eq!(3500, cpu.get_memory(0));
+    Ok(())
+}
+
+#[test]
+fn test_needs_input() -> Result<()> {
+    // Echo two inputs back.
+    let mut cpu = CPU::new(&[3, 11, 4, 11, 3, 11, 4, 11, 99, 0, 0, 0]);
+    assert_eq!(Status::NeedsInput, cpu.step()?);
+    assert_eq!(0, cpu.pc());
+
+    cpu.add_input(7);
+    assert_eq!(Status::Ready(7), cpu.step()?);
+    assert_eq!(Status::NeedsInput, cpu.step()?);
+    assert_eq!(4, cpu.pc());
+
+    cpu.add_input(9);
+    assert_eq!(Status::Ready(9), cpu.step()?);
+    assert_eq!(Status::Halted, cpu.step()?);
+    Ok(())
+}
+
+#[test]
+fn test_fork() -> Result<()> {
+    // Count up from the input, outputting every value.
+    let program = vec![3, 11, 4, 11, 1001, 11, 1, 11, 1105, 1, 2, 0];
+    let mut cpu = CPU::new(&program);
+    cpu.add_input(10);
+    assert_eq!(Status::Ready(10), cpu.step()?);
+
+    let mut fork = cpu.fork();
+    assert_eq!(Status::Ready(11), fork.step()?);
+    assert_eq!(Status::Ready(12), fork.step()?);
+    assert_eq!(Status::Ready(11), cpu.step()?);
+    assert_eq!(12, fork.get_memory(11));
+    assert_eq!(11, cpu.get_memory(11));
+    Ok(())
+}
+
+#[test]
+fn test_outputs() -> Result<()> {
+    let program = vec![3, 9, 4, 9, 3, 9, 4, 9, 99, 0];
+    let mut cpu = CPU::new(&program);
+    cpu.add_input(5);
+    let outputs: Vec<_> = cpu.outputs().collect();
+    assert_eq!(
+        vec![
+            Ok(5),
+            Err(IntcodeError::InputExhausted { at: cpu.location() })
+        ],
+        outputs
+    );
+
+    // Pick up where it left off.
+    cpu.add_input(6);
+    assert_eq!(vec![6], cpu.run_to_completion()?);
+    assert_eq!(Vec::<i64>::new(), cpu.run_to_completion()?);
+    assert_eq!(
+        Vec::<i64>::new(),
+        CPU::new(&[1101, 1, 1, 0, 99]).run_to_completion()?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_execute() -> Result<()> {
+    // A jump to itself stays put, and only the parameters an opcode has are read.
+    let mut cpu = CPU::new(&[1105, 1, 0]);
+    assert_eq!(None, cpu.execute()?);
+    assert_eq!(0, cpu.pc());
+    assert_eq!(None, cpu.execute()?);
+    assert_eq!(0, cpu.pc());
+
+    let mut cpu = CPU::new(&[104, 7, 99]);
+    assert_eq!(Some(Status::Ready(7)), cpu.execute()?);
+    assert_eq!(Some(Status::Halted), cpu.execute()?);
+    assert_eq!(2, cpu.pc());
+    Ok(())
+}
+
+#[test]
+fn test_addresses() -> Result<()> {
+    // Store the input at a huge address and read it back.
+    let mut cpu = CPU::new(&[3, 1_000_000_000_000, 4, 1_000_000_000_000, 99]);
+    cpu.add_input(5);
+    assert_eq!(Status::Ready(5), cpu.step()?);
+    assert_eq!(2, cpu.memory().pages());
+
+    Ok(())
+}
+
+#[test]
+fn test_errors() {
+    let at = |pc, instruction, relative_base| Location {
+        pc,
+        instruction,
+        relative_base,
+    };
+
+    assert_eq!(
+        Err(IntcodeError::NegativeAddress {
+            address: -3,
+            at: at(0, 1101, 0)
+        }),
+        CPU::new(&[1101, 1, 1, -3, 99]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::NegativeAddress {
+            address: -1,
+            at: at(2, 2201, -5)
+        }),
+        CPU::new(&[109, -5, 2201, 4, 0, 0, 99]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::UnknownOpcode {
+            opcode: 42,
+            at: at(3, 142, 0)
+        }),
+        CPU::new(&[1105, 1, 3, 142]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::BadParameterMode {
+            mode: 3,
+            at: at(0, 301, 0)
+        }),
+        CPU::new(&[301, 0, 0, 0, 99]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::PcOutOfBounds { at: at(4, 0, 0) }),
+        CPU::new(&[1105, 1, 4]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::PcOutOfBounds { at: at(0, 1, 0) }),
+        CPU::new(&[1, 0, 0]).step()
+    );
+
+    // Immediate mode writes only fail in strict mode.
+    let program = [11101, 2, 3, 5, 99, 0];
+    let mut cpu = CPU::new(&program);
+    assert_eq!(Ok(Status::Halted), cpu.step());
+    assert_eq!(5, cpu.get_memory(5));
+    let mut cpu = CPU::new(&program);
+    cpu.set_strict(true);
+    assert_eq!(
+        Err(IntcodeError::ImmediateWrite {
+            at: at(0, 11101, 0)
+        }),
+        cpu.step()
+    );
+    let mut cpu = CPU::new(&[103, 1, 99]);
+    cpu.set_strict(true);
+    cpu.add_input(1);
+    assert_eq!(
+        Err(IntcodeError::ImmediateWrite { at: at(0, 103, 0) }),
+        cpu.step()
+    );
+
+    // Arithmetic fails rather than wrapping around.
+    assert_eq!(
+        Err(IntcodeError::Overflow { at: at(0, 1102, 0) }),
+        CPU::new(&[1102, i64::MAX, 2, 0, 99]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::Overflow {
+            at: at(2, 109, i64::MAX)
+        }),
+        CPU::new(&[109, i64::MAX, 109, 1, 99]).step()
+    );
+    assert_eq!(
+        Err(IntcodeError::Overflow {
+            at: at(2, 204, i64::MAX)
+        }),
+        CPU::new(&[109, i64::MAX, 204, 1, 99]).step()
+    );
+
+    // Tracing takes the general path through `execute`, errors come out the same.
+    for program in &[
+        vec![1101, 1, 1, -3, 99],
+        vec![109, -5, 2201, 4, 0, 0, 99],
+        vec![1105, 1, 3, 142],
+        vec![301, 0, 0, 0, 99],
+        vec![1, 0, 0],
+        vec![109, i64::MAX, 204, 1, 99],
+        vec![1102, i64::MAX, 2, 0, 99],
+    ] {
+        let mut traced = CPU::new(program);
+        traced.set_tracer(Box::new(Vec::<Trace>::new()));
+        assert_eq!(CPU::new(program).step(), traced.step());
+    }
+
+    let err = run_program(&[3, 0, 99], &[]).unwrap_err();
+    assert_eq!(
+        Some(&IntcodeError::InputExhausted { at: at(0, 3, 0) }),
+        err.downcast_ref::<IntcodeError>()
+    );
+    assert_eq!(
+        "ran out of inputs at pc 0 (instruction 3, relative base 0)",
+        err.to_string()
+    );
+}
+
+#[test]
+fn from_opcode_mode() {
+    assert_eq!(
+        OpCodeMode {
+            opcode: 2,
+            p1: 0,
+            p2: 1,
+            p3: 0
+        },
+        1002.into()
+    );
+
+    assert_eq!(
+        OpCodeMode {
+            opcode: 99,
+            p1: 0,
+            p2: 0,
+            p3: 1
+        },
+        10099.into()
+    );
+}
@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::intcode::disasm::{decode, Line, Op};
+use crate::intcode::Parameter;
+
+/// A straight run of instructions, only entered at the top and left at the bottom.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Block {
+    pub start: usize,
+    /// One past the last word of the last instruction.
+    pub end: usize,
+    pub lines: Vec<Line>,
+    /// Blocks control can move to next, fallthrough first.
+    pub successors: Vec<usize>,
+    /// The block ends in a jump whose target is only known at run time.
+    pub indirect: bool,
+}
+
+/// An instruction that writes to `target`, a word of reachable code or a reachable
+/// address that only decodes once it's been written.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SelfModification {
+    pub at: usize,
+    pub target: usize,
+}
+
+/// What can be worked out about a program without running it.
+///
+/// Code is found by following control flow from address 0. Only jumps with an
+/// immediate target can be followed, so code reached solely through computed jumps,
+/// e.g. returns from functions, shows up as data.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub blocks: BTreeMap<usize, Block>,
+    /// Targets of direct jumps.
+    pub jump_targets: BTreeSet<usize>,
+    pub self_modifying: Vec<SelfModification>,
+    /// Ranges not covered by any reachable instruction.
+    pub data: Vec<Range<usize>>,
+    /// Reachable addresses that don't decode to an instruction.
+    pub invalid: BTreeSet<usize>,
+}
+
+/// Where control can go after `line`: whether it can fall through to the next
+/// instruction, where it can jump directly and whether it can jump somewhere unknown.
+pub fn flow(line: &Line) -> (bool, Option<usize>, bool) {
+    let (op, params) = match line {
+        Line::Instruction { op, params, .. } => (*op, params),
+        Line::Data { .. } => return (false, None, false),
+    };
+    match op {
+        Op::Hlt => (false, None, false),
+        Op::Jnz | Op::Jz => {
+            let taken = match params[0] {
+                Parameter::ImmediateMode(test) => Some((test != 0) == (op == Op::Jnz)),
+                _ => None,
+            };
+            let falls = taken != Some(true);
+            match (taken, params[1]) {
+                (Some(false), _) => (falls, None, false),
+                (_, Parameter::ImmediateMode(target)) if target >= 0 => {
+                    (falls, Some(target as usize), false)
+                }
+                _ => (falls, None, true),
+            }
+        }
+        _ => (true, None, false),
+    }
+}
+
+/// The address an instruction writes to, if it doesn't depend on the relative base.
+fn write_target(line: &Line) -> Option<usize> {
+    match line {
+        Line::Instruction { op, params, .. } if op.writes() => match params.last()? {
+            Parameter::PositionMode(n) | Parameter::ImmediateMode(n) if *n >= 0 => {
+                Some(*n as usize)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn analyze(program: &[i64]) -> Analysis {
+    // Find every reachable instruction.
+    let mut lines = BTreeMap::new();
+    let mut jump_targets = BTreeSet::new();
+    let mut invalid = BTreeSet::new();
+    let mut work = vec![0];
+    while let Some(address) = work.pop() {
+        if lines.contains_key(&address) || invalid.contains(&address) {
+            continue;
+        }
+        let line = match decode(program, address) {
+            Some(line) => line,
+            None => {
+                invalid.insert(address);
+                continue;
+            }
+        };
+        let (falls, target, _) = flow(&line);
+        if falls {
+            work.push(address + line.len());
+        }
+        if let Some(target) = target {
+            jump_targets.insert(target);
+            work.push(target);
+        }
+        lines.insert(address, line);
+    }
+
+    let mut code = BTreeSet::new();
+    for line in lines.values() {
+        code.extend(line.address()..line.address() + line.len());
+    }
+
+    // Blocks start at the entry, at jump targets and after jumps.
+    let mut leaders: BTreeSet<usize> = jump_targets.clone();
+    leaders.insert(0);
+    for line in lines.values() {
+        if let Line::Instruction {
+            op: Op::Jnz | Op::Jz,
+            ..
+        } = line
+        {
+            leaders.insert(line.address() + line.len());
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|a| lines.contains_key(a)) {
+        let mut block = Block {
+            start,
+            end: start,
+            lines: Vec::new(),
+            successors: Vec::new(),
+            indirect: false,
+        };
+        while let Some(line) = lines.get(&block.end) {
+            block.end += line.len();
+            block.lines.push(line.clone());
+            let (falls, target, indirect) = flow(line);
+            let ends = !falls || target.is_some() || indirect || leaders.contains(&block.end);
+            if ends {
+                if falls && lines.contains_key(&block.end) {
+                    block.successors.push(block.end);
+                }
+                block
+                    .successors
+                    .extend(target.filter(|t| lines.contains_key(t)));
+                block.indirect = indirect;
+                break;
+            }
+        }
+        blocks.insert(start, block);
+    }
+
+    let mut self_modifying = Vec::new();
+    for line in lines.values() {
+        if let Some(target) = write_target(line).filter(|t| code.contains(t) || invalid.contains(t))
+        {
+            self_modifying.push(SelfModification {
+                at: line.address(),
+                target,
+            });
+        }
+    }
+
+    let mut data: Vec<Range<usize>> = Vec::new();
+    for address in (0..program.len()).filter(|a| !code.contains(a)) {
+        match data.last_mut() {
+            Some(range) if range.end == address => range.end += 1,
+            _ => data.push(address..address + 1),
+        }
+    }
+
+    Analysis {
+        blocks,
+        jump_targets,
+        self_modifying,
+        data,
+        invalid,
+    }
+}
+
+impl Analysis {
+    /// Whether `address` is part of a reachable instruction.
+    pub fn is_code(&self, address: usize) -> bool {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(_, block)| address < block.end)
+    }
+
+    /// Render the control-flow graph as Graphviz DOT, one node per block.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for line in &block.lines {
+                write!(label, "{}: {}\\l", line.address(), line).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+            for successor in &block.successors {
+                writeln!(dot, "    b{} -> b{};", block.start, successor).unwrap();
+            }
+            if block.indirect {
+                writeln!(dot, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+        if self.blocks.values().any(|block| block.indirect) {
+            dot.push_str("    indirect [shape=plaintext, label=\"?\"];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[test]
+fn test_analyze() {
+    let program = vec![
+        3, 20, // 0: IN -> [20]
+        1006, 20, 16, // 2: JZ [20], #16
+        1001, 20, -1, 20, // 5: ADD [20], #-1 -> [20]
+        1101, 0, 2, 15, // 9: ADD #0, #2 -> [15], patches the jump below
+        1105, 1, 2,  // 13: JNZ #1, #2
+        99, // 16: HLT
+        42, 43,
+    ];
+    let analysis = analyze(&program);
+
+    let starts: Vec<usize> = analysis.blocks.keys().copied().collect();
+    assert_eq!(vec![0, 2, 5, 16], starts);
+    assert_eq!(vec![2], analysis.blocks[&0].successors);
+    assert_eq!(vec![5, 16], analysis.blocks[&2].successors);
+    assert_eq!(vec![2], analysis.blocks[&5].successors);
+    assert_eq!(3, analysis.blocks[&5].lines.len());
+    assert_eq!(
+        vec![2, 16],
+        analysis.jump_targets.iter().copied().collect::<Vec<_>>()
+    );
+    assert!(analysis.invalid.is_empty());
+
+    assert_eq!(
+        vec![SelfModification { at: 9, target: 15 }],
+        analysis.self_modifying
+    );
+    assert_eq!(vec![17..19], analysis.data);
+    assert!(analysis.is_code(15));
+    assert!(!analysis.is_code(17));
+
+    let dot = analysis.to_dot();
+    assert!(dot.starts_with("digraph cfg {\n"));
+    assert!(dot.contains("    b0 [label=\"0: IN -> [20]\\l\"];\n    b0 -> b2;\n"));
+    assert!(dot.contains("    b2 -> b5;\n    b2 -> b16;\n"));
+    assert!(!dot.contains("indirect"));
+}
+
+#[test]
+fn test_indirect() {
+    // Call a function that returns through the stack, like the day 9 program does.
+    let program = vec![
+        109, 20, // 0: ARB #20
+        21101, 8, 0, 0, // 2: ADD #8, #0 -> [rb+0], push the return address
+        1105, 1, 10, // 6: JNZ #1, #10
+        99, // 9: HLT, only reached by returning
+        2105, 1, 0, // 10: JNZ #1, [rb+0]
+    ];
+    let analysis = analyze(&program);
+    assert_eq!(
+        vec![0, 10],
+        analysis.blocks.keys().copied().collect::<Vec<_>>()
+    );
+    assert!(analysis.blocks[&10].indirect);
+    assert!(analysis.blocks[&10].successors.is_empty());
+    assert_eq!(vec![9..10], analysis.data);
+    assert!(analysis
+        .to_dot()
+        .contains("b10 -> indirect [style=dashed];"));
+}
+
+#[test]
+fn test_day5() -> anyhow::Result<()> {
+    // The diagnostic program finishes writing its own third instruction from the input.
+    let analysis = analyze(&crate::intcode::read_program("input/day5.txt")?);
+    assert_eq!(
+        vec![6],
+        analysis.invalid.iter().copied().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![SelfModification { at: 2, target: 6 }],
+        analysis.self_modifying
+    );
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use crate::intcode::error::IntcodeError;
+use crate::intcode::{Status, CPU};
+
+/// What ended a run of text, see `CPU::read_text`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum End {
+    Newline,
+    /// The program is waiting for input, the text is usually a prompt.
+    NeedsInput,
+    /// An output outside the ASCII range, passed through as a number.
+    Value(i64),
+    Halted,
+}
+
+/// Text output by a program and what it stopped on.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Text {
+    pub text: String,
+    pub end: End,
+}
+
+/// The character for an output, if it's in the ASCII range.
+pub fn to_char(value: i64) -> Option<char> {
+    if (0..=127).contains(&value) {
+        Some(value as u8 as char)
+    } else {
+        None
+    }
+}
+
+impl CPU {
+    /// Queue `line` as character codes followed by a newline.
+    pub fn add_line(&mut self, line: &str) {
+        for c in line.trim_end_matches(&['\r', '\n'][..]).chars() {
+            self.add_input(c as i64);
+        }
+        self.add_input('\n' as i64);
+    }
+
+    /// Collect output as text up to a newline, a prompt for input, a non-ASCII
+    /// value or the program halting.
+    pub fn read_text(&mut self) -> Result<Text, IntcodeError> {
+        let mut text = String::new();
+        let end = loop {
+            match self.step()? {
+                Status::Ready(value) => match to_char(value) {
+                    Some('\n') => break End::Newline,
+                    Some(c) => text.push(c),
+                    None => break End::Value(value),
+                },
+                Status::NeedsInput => break End::NeedsInput,
+                Status::Halted => break End::Halted,
+            }
+        };
+        Ok(Text { text, end })
+    }
+}
+
+#[test]
+fn test_read_text() -> anyhow::Result<()> {
+    use crate::intcode::asm::assemble;
+
+    let program = assemble(
+        "
+                OUT #72
+                OUT #105
+                OUT #10
+                OUT #62
+        loop:   IN -> [c]
+                EQ [c], #10 -> [flag]
+                JNZ [flag], #done
+                OUT [c]
+                JZ #0, #loop
+        done:   OUT #1000
+                HLT
+        c:      DB 0
+        flag:   DB 0
+        ",
+    )?;
+
+    let text = |text: &str, end| Text {
+        text: text.to_string(),
+        end,
+    };
+    let mut cpu = CPU::new(&program);
+    assert_eq!(text("Hi", End::Newline), cpu.read_text()?);
+    assert_eq!(text(">", End::NeedsInput), cpu.read_text()?);
+    cpu.add_line("ok");
+    assert_eq!(text("ok", End::Value(1000)), cpu.read_text()?);
+    assert_eq!(text("", End::Halted), cpu.read_text()?);
+    Ok(())
+}
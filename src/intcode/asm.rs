@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+
+use crate::intcode::op::Op;
+
+/// A value that may refer to a label, e.g. `12`, `loop` or `buffer+3`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+/// An operand written as `#value`, `[value]` or `[rb+n]`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+enum Operand {
+    Position(Value),
+    Immediate(Value),
+    Relative(Value),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Item {
+    Instruction(Op, Vec<Operand>),
+    Data(Vec<Value>),
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    name != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(text: &str) -> Result<Value> {
+    let text = text.trim();
+    if let Ok(n) = text.parse::<i64>() {
+        return Ok(Value::Number(n));
+    }
+
+    // Split a trailing offset off a label, `name+3` or `name-3`.
+    let (name, offset) = match text.find(['+', '-']) {
+        Some(i) => {
+            let offset: i64 = text[i + 1..].trim().parse()?;
+            let offset = if &text[i..=i] == "-" { -offset } else { offset };
+            (text[..i].trim(), offset)
+        }
+        None => (text, 0),
+    };
+    if !is_label(name) {
+        return Err(anyhow!("invalid value {:?}", text));
+    }
+    Ok(Value::Label(name.to_string(), offset))
+}
+
+fn parse_operand(text: &str) -> Result<Operand> {
+    let text = text.trim();
+    if let Some(value) = text.strip_prefix('#') {
+        return Ok(Operand::Immediate(parse_value(value)?));
+    }
+
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|t| t.strip_suffix(']'))
+        .context(format!("invalid operand {:?}", text))?
+        .trim();
+    match inner.strip_prefix("rb") {
+        Some(offset) if offset.starts_with(['+', '-']) => {
+            let n: i64 = offset[1..].trim().parse()?;
+            Ok(Operand::Relative(Value::Number(
+                if offset.starts_with('-') { -n } else { n },
+            )))
+        }
+        _ => Ok(Operand::Position(parse_value(inner)?)),
+    }
+}
+
+fn parse_instruction(op: Op, rest: &str) -> Result<Vec<Operand>> {
+    let (reads, write) = match rest.find("->") {
+        Some(i) => (&rest[..i], Some(&rest[i + 2..])),
+        None => (rest, None),
+    };
+
+    let mut operands = reads
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(parse_operand)
+        .collect::<Result<Vec<Operand>>>()?;
+
+    match (op.writes(), write) {
+        (true, Some(target)) => operands.push(parse_operand(target)?),
+        (true, None) => return Err(anyhow!("{} needs a write target", op.mnemonic())),
+        (false, Some(_)) => return Err(anyhow!("{} doesn't write", op.mnemonic())),
+        (false, None) => {}
+    }
+
+    if operands.len() != op.arity() {
+        return Err(anyhow!(
+            "{} takes {} operands, got {}",
+            op.mnemonic(),
+            op.arity(),
+            operands.len()
+        ));
+    }
+    Ok(operands)
+}
+
+/// Parse a single source line into its labels and an optional item.
+fn parse_line(line: &str) -> Result<(Vec<&str>, Option<Item>)> {
+    let mut text = match line.find(';') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+    .trim();
+
+    let mut labels = Vec::new();
+    while let Some(i) = text.find(':') {
+        labels.push(text[..i].trim());
+        text = text[i + 1..].trim();
+    }
+    if text.is_empty() {
+        return Ok((labels, None));
+    }
+
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    };
+    if mnemonic.eq_ignore_ascii_case("db") {
+        let values = rest
+            .split(',')
+            .map(parse_value)
+            .collect::<Result<Vec<Value>>>()?;
+        return Ok((labels, Some(Item::Data(values))));
+    }
+
+    let op = Op::from_mnemonic(mnemonic).context(format!("unknown mnemonic {}", mnemonic))?;
+    let operands = parse_instruction(op, rest)?;
+    Ok((labels, Some(Item::Instruction(op, operands))))
+}
+
+fn resolve(value: &Value, labels: &HashMap<String, i64>) -> Result<i64> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        Value::Label(name, offset) => labels
+            .get(name)
+            .map(|address| address + offset)
+            .context(format!("undefined label {}", name)),
+    }
+}
+
+/// Assemble source into an Intcode program.
+///
+/// The syntax is the one the disassembler prints, `ADD [12], #5 -> [rb+3]`, plus:
+///
+/// * `name:` defines a label at the current address, usable as a value anywhere
+///   (`JNZ [flag], #loop`, `[counter]`, `#buffer+2`).
+/// * `12:` asserts the current address, so a listing can be fed straight back in.
+/// * `DB 1, 2, name` emits raw words.
+/// * `;` starts a comment.
+pub fn assemble(source: &str) -> Result<Vec<i64>> {
+    let mut labels: HashMap<String, i64> = HashMap::new();
+    let mut items = Vec::new();
+    let mut address = 0;
+
+    for (n, line) in source.lines().enumerate() {
+        let (names, item) = parse_line(line).context(format!("line {}", n + 1))?;
+        for name in names {
+            if let Ok(expected) = name.parse::<i64>() {
+                if expected != address {
+                    return Err(anyhow!(
+                        "line {}: expected address {} but at {}",
+                        n + 1,
+                        expected,
+                        address
+                    ));
+                }
+            } else if !is_label(name) {
+                return Err(anyhow!("line {}: invalid label {:?}", n + 1, name));
+            } else if labels.insert(name.to_string(), address).is_some() {
+                return Err(anyhow!("line {}: duplicate label {}", n + 1, name));
+            }
+        }
+
+        if let Some(item) = item {
+            address += match &item {
+                Item::Instruction(_, operands) => operands.len() as i64 + 1,
+                Item::Data(values) => values.len() as i64,
+            };
+            items.push((n + 1, item));
+        }
+    }
+
+    let mut program = Vec::new();
+    for (n, item) in items {
+        match item {
+            Item::Instruction(op, operands) => {
+                let mut code = i64::from(op.opcode());
+                let mut words = Vec::new();
+                for (i, operand) in operands.iter().enumerate() {
+                    let (mode, value) = match operand {
+                        Operand::Position(v) => (0, v),
+                        Operand::Immediate(v) => (1, v),
+                        Operand::Relative(v) => (2, v),
+                    };
+                    code += mode * 10i64.pow(i as u32 + 2);
+                    words.push(resolve(value, &labels).context(format!("line {}", n))?);
+                }
+                program.push(code);
+                program.extend(words);
+            }
+            Item::Data(values) => {
+                for value in values {
+                    program.push(resolve(&value, &labels).context(format!("line {}", n))?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+#[test]
+fn test_assemble() -> Result<()> {
+    use crate::intcode::{Status, CPU};
+
+    let source = "
+        ; Output the input, doubled, until a zero is read.
+        loop:   IN -> [value]
+                JZ [value], #done
+                MUL [value], #2 -> [value]
+                OUT [value]
+                JNZ #1, #loop
+        done:   HLT
+        value:  DB 0
+    ";
+    let program = assemble(source)?;
+    assert_eq!(
+        vec![3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1105, 1, 0, 99, 0],
+        program
+    );
+
+    let mut cpu = CPU::new(&program);
+    for i in &[4, 21, 0] {
+        cpu.add_input(*i);
+    }
+    assert_eq!(Status::Ready(8), cpu.step()?);
+    assert_eq!(Status::Ready(42), cpu.step()?);
+    assert_eq!(Status::Halted, cpu.step()?);
+
+    assert_eq!(
+        vec![204, -1, 109, 3, 21101, 5, 7, -2, 2, 12, 13, 14, 11, 12],
+        assemble(
+            "OUT [rb-1]\nARB #3\nADD #5, #7 -> [rb-2]\nMUL [x], [x+1] -> [x + 2]\nx: DB 11, 12"
+        )?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_round_trip() -> Result<()> {
+    use crate::intcode::{disasm::listing, read_program};
+
+    for day in &["day2", "day5", "day7", "day9"] {
+        let program = read_program(&format!("input/{}.txt", day))?;
+        assert_eq!(program, assemble(&listing(&program))?);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_errors() {
+    assert!(assemble("FOO [1]").is_err());
+    assert!(assemble("ADD [1], [2]").is_err());
+    assert!(assemble("OUT [1] -> [2]").is_err());
+    assert!(assemble("JNZ #1, #nowhere").is_err());
+    assert!(assemble("a: HLT\na: HLT").is_err());
+    assert!(assemble("HLT\n0: HLT").is_err());
+}
@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use crate::intcode::snapshot::Snapshot;
+use crate::intcode::{Status, CPU};
+
+/// Why the debugger handed control back.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// A single step finished without anything interesting happening.
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: i64,
+        new: i64,
+    },
+    Output(i64),
+    NeedsInput,
+    Halted,
+}
+
+/// Wraps a CPU with breakpoints on the program counter and watchpoints on memory.
+///
+/// Every step is recorded in the CPU's undo log, so execution can be rewound to an
+/// earlier instruction count or to the last write of an address. The log only goes
+/// back so far, see `CPU::set_history_limit`.
+pub struct Debugger {
+    cpu: CPU,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, i64>,
+    outputs: Vec<i64>,
+    halted: bool,
+}
+
+impl Debugger {
+    pub fn new(program: &[i64]) -> Debugger {
+        Debugger::from_cpu(CPU::new(program))
+    }
+
+    pub fn from_cpu(mut cpu: CPU) -> Debugger {
+        cpu.record_history(true);
+        Debugger {
+            cpu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            outputs: Vec::new(),
+            halted: false,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    /// Start over from `snapshot`. Outputs, breakpoints and watchpoints belong to the
+    /// old session, so they're cleared.
+    pub fn load(&mut self, snapshot: &Snapshot) {
+        self.cpu.restore(snapshot);
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.outputs.clear();
+        self.halted = false;
+    }
+
+    /// Everything the program has output so far.
+    pub fn outputs(&self) -> &[i64] {
+        &self.outputs
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &usize> {
+        self.breakpoints.iter()
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &usize> {
+        self.watchpoints.keys()
+    }
+
+    /// Toggle a breakpoint, returning whether it is now set.
+    pub fn toggle_breakpoint(&mut self, pc: usize) -> bool {
+        if self.breakpoints.remove(&pc) {
+            false
+        } else {
+            self.breakpoints.insert(pc)
+        }
+    }
+
+    /// Toggle a watchpoint, returning whether it is now set.
+    pub fn toggle_watchpoint(&mut self, address: usize) -> bool {
+        if self.watchpoints.remove(&address).is_some() {
+            false
+        } else {
+            let value = self.cpu.get_memory(address);
+            self.watchpoints.insert(address, value);
+            true
+        }
+    }
+
+    /// Execute exactly one instruction. Once the program has halted this does nothing.
+    pub fn step(&mut self) -> Result<Stop> {
+        if self.halted {
+            return Ok(Stop::Halted);
+        }
+        let stop = match self.cpu.execute()? {
+            Some(Status::Ready(value)) => {
+                self.outputs.push(value);
+                Stop::Output(value)
+            }
+            Some(Status::NeedsInput) => Stop::NeedsInput,
+            Some(Status::Halted) => {
+                self.halted = true;
+                Stop::Halted
+            }
+            None => Stop::Stepped,
+        };
+
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.cpu.get_memory(*address);
+            if new != *old {
+                let stop = Stop::Watchpoint {
+                    address: *address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Ok(stop);
+            }
+        }
+
+        Ok(stop)
+    }
+
+    /// Instructions executed so far.
+    pub fn instructions(&self) -> u64 {
+        self.cpu.usage().instructions
+    }
+
+    /// Rewind to the point where `count` instructions had been executed.
+    pub fn rewind(&mut self, count: u64) -> Result<()> {
+        let earliest = self.instructions() - self.cpu.history().len() as u64;
+        if count < earliest || count > self.instructions() {
+            return Err(anyhow!(
+                "can only rewind to between {} and {} instructions",
+                earliest,
+                self.instructions()
+            ));
+        }
+        while self.instructions() > count {
+            self.undo();
+        }
+        self.refresh_watchpoints();
+        Ok(())
+    }
+
+    /// Rewind to just before the last write to `address`, returning the instruction
+    /// count there, or `None` if no recorded instruction wrote it.
+    pub fn rewind_to_write(&mut self, address: usize) -> Option<u64> {
+        let history = self.cpu.history();
+        let index = history
+            .iter()
+            .rposition(|undo| undo.write.is_some_and(|write| write.address == address))?;
+        for _ in index..history.len() {
+            self.undo();
+        }
+        self.refresh_watchpoints();
+        Some(self.instructions())
+    }
+
+    fn undo(&mut self) {
+        if let Some(undo) = self.cpu.undo() {
+            self.halted = false;
+            if undo.output.is_some() {
+                self.outputs.pop();
+            }
+        }
+    }
+
+    /// Watchpoints compare against the value they last saw, which a rewind changes.
+    fn refresh_watchpoints(&mut self) {
+        for (address, value) in self.watchpoints.iter_mut() {
+            *value = self.cpu.get_memory(*address);
+        }
+    }
+
+    /// Run until a breakpoint, watchpoint, output, or until the program blocks or halts.
+    ///
+    /// The instruction under the program counter always runs, so continuing from a
+    /// breakpoint doesn't stop on it straight away.
+    pub fn resume(&mut self) -> Result<Stop> {
+        loop {
+            let stop = self.step()?;
+            if stop != Stop::Stepped {
+                return Ok(stop);
+            }
+            let pc = self.cpu.pc();
+            if self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_breakpoints() -> Result<()> {
+    use crate::intcode::asm::assemble;
+
+    let program = assemble(
+        "
+        loop:   ADD [count], #1 -> [count]
+        check:  EQ [count], #3 -> [flag]
+                JZ [flag], #loop
+                OUT [count]
+                HLT
+        count:  DB 0
+        flag:   DB 0
+        ",
+    )?;
+    let check = 4;
+
+    let mut debugger = Debugger::new(&program);
+    assert!(debugger.toggle_breakpoint(check));
+    assert_eq!(Stop::Breakpoint(check), debugger.resume()?);
+    assert_eq!(Stop::Breakpoint(check), debugger.resume()?);
+    assert!(!debugger.toggle_breakpoint(check));
+
+    assert_eq!(Stop::Stepped, debugger.step()?);
+    assert_eq!(Stop::Stepped, debugger.step()?);
+    assert_eq!(0, debugger.cpu().pc());
+
+    assert!(debugger.toggle_watchpoint(14));
+    assert_eq!(
+        Stop::Watchpoint {
+            address: 14,
+            old: 2,
+            new: 3
+        },
+        debugger.resume()?
+    );
+    assert_eq!(Stop::Output(3), debugger.resume()?);
+    assert_eq!(Stop::Halted, debugger.resume()?);
+    assert_eq!(Stop::Halted, debugger.resume()?);
+    assert_eq!(&[3], debugger.outputs());
+
+    // Loading starts a fresh session.
+    debugger.toggle_breakpoint(check);
+    debugger.load(&CPU::new(&program).snapshot());
+    assert!(debugger.outputs().is_empty());
+    assert_eq!(0, debugger.breakpoints().count());
+    assert_eq!(0, debugger.watchpoints().count());
+    assert_eq!(Stop::Output(3), debugger.resume()?);
+    Ok(())
+}
+
+#[test]
+fn test_rewind() -> Result<()> {
+    use crate::intcode::read_program;
+
+    // Day 9's BOOST program in test mode outputs a single keycode.
+    let program = read_program("input/day9.txt")?;
+    let mut debugger = Debugger::new(&program);
+    debugger.cpu_mut().add_input(1);
+    let keycode = match debugger.resume()? {
+        Stop::Output(value) => value,
+        other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(Stop::Halted, debugger.resume()?);
+    let total = debugger.instructions();
+    let logged = debugger.cpu().history().len();
+    assert_eq!(Stop::Halted, debugger.resume()?);
+    assert_eq!(total, debugger.instructions());
+    assert_eq!(logged, debugger.cpu().history().len());
+
+    // Go back to the last write of the output address, then all the way to the start.
+    let at = debugger.rewind_to_write(1000);
+    assert!(at.is_some_and(|at| at < total));
+    assert!(debugger.outputs().is_empty());
+    assert_eq!(None, debugger.rewind_to_write(1_000_000));
+    assert!(debugger.rewind(total).is_err());
+    debugger.rewind(0)?;
+    assert_eq!(0, debugger.cpu().pc());
+    assert_eq!(0, debugger.cpu().relative_base());
+    assert_eq!(program, debugger.cpu().memory().to_vec());
+
+    // Replaying consumes the input again and gives the same answer.
+    assert_eq!(Stop::Output(keycode), debugger.resume()?);
+    assert_eq!(Stop::Halted, debugger.resume()?);
+    assert_eq!(total, debugger.instructions());
+    Ok(())
+}
@@ -0,0 +1,1207 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::intcode::analysis::flow;
+use crate::intcode::disasm::{decode, Line, Op};
+use crate::intcode::Parameter;
+
+/// Binary operators, the last two only come from negating a condition.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinOp {
+    Add,
+    Mul,
+    Lt,
+    Eq,
+    Ge,
+    Ne,
+}
+
+impl BinOp {
+    fn from_op(op: Op) -> Option<BinOp> {
+        match op {
+            Op::Add => Some(BinOp::Add),
+            Op::Mul => Some(BinOp::Mul),
+            Op::Lt => Some(BinOp::Lt),
+            Op::Eq => Some(BinOp::Eq),
+            _ => None,
+        }
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul => 3,
+            BinOp::Add => 2,
+            _ => 1,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Mul => "*",
+            BinOp::Lt => "<",
+            BinOp::Eq => "==",
+            BinOp::Ge => ">=",
+            BinOp::Ne => "!=",
+        }
+    }
+
+    /// The result, `None` where the CPU would fail with an overflow.
+    fn eval(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Mul => a.checked_mul(b),
+            BinOp::Lt => Some((a < b) as i64),
+            BinOp::Eq => Some((a == b) as i64),
+            BinOp::Ge => Some((a >= b) as i64),
+            BinOp::Ne => Some((a != b) as i64),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Expr {
+    Const(i64),
+    /// A parameter, local or memory cell, by the name it's printed with.
+    Var(String),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Build `a op b`, folding constants and dropping identities.
+    fn binary(op: BinOp, a: Expr, b: Expr) -> Expr {
+        if let (Expr::Const(x), Expr::Const(y)) = (&a, &b) {
+            if let Some(value) = op.eval(*x, *y) {
+                return Expr::Const(value);
+            }
+        }
+        match (op, &a, &b) {
+            (BinOp::Add, Expr::Const(0), _) | (BinOp::Mul, Expr::Const(1), _) => b,
+            (BinOp::Add, _, Expr::Const(0)) | (BinOp::Mul, _, Expr::Const(1)) => a,
+            (BinOp::Mul, Expr::Const(0), _) | (BinOp::Mul, _, Expr::Const(0)) => Expr::Const(0),
+            _ => Expr::Binary(op, Box::new(a), Box::new(b)),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, ..) => op.precedence(),
+            _ => 4,
+        }
+    }
+
+    fn is_condition(&self) -> bool {
+        self.precedence() == 1
+    }
+
+    /// The condition under which a jump testing this value for non-zero is taken.
+    fn truth(self) -> Expr {
+        match self {
+            Expr::Const(_) => self,
+            e if e.is_condition() => e,
+            e => Expr::Binary(BinOp::Ne, Box::new(e), Box::new(Expr::Const(0))),
+        }
+    }
+
+    fn negate(self) -> Expr {
+        match self {
+            Expr::Const(c) => Expr::Const((c == 0) as i64),
+            Expr::Binary(op, a, b) if op.precedence() == 1 => {
+                let op = match op {
+                    BinOp::Lt => BinOp::Ge,
+                    BinOp::Ge => BinOp::Lt,
+                    BinOp::Eq => BinOp::Ne,
+                    _ => BinOp::Eq,
+                };
+                Expr::Binary(op, a, b)
+            }
+            e => Expr::Binary(BinOp::Eq, Box::new(e), Box::new(Expr::Const(0))),
+        }
+    }
+
+    fn mentions(&self, name: &str) -> bool {
+        match self {
+            Expr::Const(_) => false,
+            Expr::Var(var) => var == name,
+            Expr::Binary(_, a, b) => a.mentions(name) || b.mentions(name),
+        }
+    }
+}
+
+/// Wraps an operand in parentheses if it binds looser than its operator.
+struct Operand<'a>(&'a Expr, u8);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Operand(expr, precedence) = *self;
+        if expr.precedence() < precedence || (expr.is_condition() && precedence == 1) {
+            write!(f, "({})", expr)
+        } else {
+            write!(f, "{}", expr)
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Const(value) => write!(f, "{}", value),
+            Expr::Var(name) => write!(f, "{}", name),
+            Expr::Binary(op, a, b) => {
+                let precedence = op.precedence();
+                match (op, &**b) {
+                    (BinOp::Add, Expr::Const(c)) if *c < 0 && *c != i64::MIN => {
+                        write!(f, "{} - {}", Operand(a, precedence), -c)
+                    }
+                    _ => write!(
+                        f,
+                        "{} {} {}",
+                        Operand(a, precedence),
+                        op.symbol(),
+                        Operand(b, precedence)
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// A statement, along with the address of the first instruction it came from.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Stmt {
+    pub address: usize,
+    pub kind: Kind,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Kind {
+    Assign(String, Expr),
+    Input(String),
+    Output(Expr),
+    /// Move the relative base outside of a recognized stack frame.
+    Arb(Expr),
+    Call {
+        target: Option<String>,
+        function: usize,
+        args: Vec<Expr>,
+    },
+    Return(Option<Expr>),
+    Halt,
+    If {
+        cond: Expr,
+        then: Vec<Stmt>,
+        otherwise: Vec<Stmt>,
+    },
+    /// Loops forever, left with `Break`.
+    Loop(Vec<Stmt>),
+    Break,
+    Continue,
+    Goto(usize),
+    /// A jump to a computed address that isn't a return.
+    Jump(Expr),
+    /// A reachable word that doesn't decode, usually one the program writes first.
+    Unknown(i64),
+    /// Nothing left after folding, kept so a label has somewhere to go.
+    Nop,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub entry: usize,
+    pub params: Vec<String>,
+    /// Locals in the stack frame, followed by the arguments of calls it makes.
+    pub locals: Vec<String>,
+    pub body: Vec<Stmt>,
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        function_name(self.entry)
+    }
+}
+
+fn function_name(entry: usize) -> String {
+    if entry == 0 {
+        "main".to_string()
+    } else {
+        format!("f{}", entry)
+    }
+}
+
+/// A whole program lifted to pseudo-code, see `decompile`.
+#[derive(Debug, Clone)]
+pub struct Decompiled {
+    pub functions: Vec<Function>,
+}
+
+impl Decompiled {
+    pub fn function(&self, entry: usize) -> Option<&Function> {
+        self.functions.iter().find(|f| f.entry == entry)
+    }
+}
+
+/// A call through the stack: the return address is written to `[rb+0]`, then an
+/// unconditional jump goes to the function.
+#[derive(Debug, Clone, Copy)]
+struct Call {
+    function: usize,
+    returns_to: usize,
+}
+
+fn call(write: &Line, jump: &Line) -> Option<Call> {
+    let returns_to = match write {
+        Line::Instruction {
+            op,
+            params,
+            address,
+        } => match (BinOp::from_op(*op), &params[..]) {
+            (
+                Some(op @ BinOp::Add) | Some(op @ BinOp::Mul),
+                &[Parameter::ImmediateMode(a), Parameter::ImmediateMode(b), Parameter::RelativeMode(0)],
+            ) if op.eval(a, b) == Some((address + 7) as i64) => address + 7,
+            _ => return None,
+        },
+        Line::Data { .. } => return None,
+    };
+    match (jump, flow(jump)) {
+        (Line::Instruction { address, .. }, (false, Some(function), false))
+            if *address + 3 == returns_to =>
+        {
+            Some(Call {
+                function,
+                returns_to,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// An unconditional jump to the address in `[rb+0]`.
+fn is_return_jump(line: &Line) -> bool {
+    matches!(
+        line,
+        Line::Instruction { op: Op::Jnz | Op::Jz, params, .. }
+            if params[1] == Parameter::RelativeMode(0) && !flow(line).0
+    )
+}
+
+/// The address an arithmetic instruction writes to, if it doesn't depend on the
+/// relative base.
+fn fixed_write(line: &Line) -> Option<usize> {
+    match line {
+        Line::Instruction { op, params, .. } if BinOp::from_op(*op).is_some() => match params[2] {
+            Parameter::PositionMode(x) | Parameter::ImmediateMode(x) if x >= 0 => Some(x as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The parameters of an instruction, split into what it reads and what it writes.
+fn operands(line: &Line) -> (&[Parameter], Option<Parameter>) {
+    match line {
+        Line::Instruction { op, params, .. } => (
+            &params[..op.reads()],
+            params.last().filter(|_| op.writes()).copied(),
+        ),
+        Line::Data { .. } => (&[], None),
+    }
+}
+
+/// What's known about a function before lifting its body.
+struct Info {
+    entry: usize,
+    /// Every instruction reachable from the entry without following calls, words that
+    /// don't decode are kept as data.
+    lines: BTreeMap<usize, Line>,
+    /// Calls by the address of their return address write.
+    calls: BTreeMap<usize, Call>,
+    /// Size of the stack frame set up by the first instruction, if the function keeps
+    /// to the calling convention.
+    frame: Option<i64>,
+    /// Leading frame slots that are read before being written, i.e. the arguments.
+    params: usize,
+    /// Whether the first frame slot is written, which is where the result goes.
+    returns: bool,
+    /// Frame slots that are used, the return address slot being 0.
+    slots: BTreeSet<i64>,
+}
+
+impl Info {
+    fn new(program: &[i64], entry: usize) -> Info {
+        let mut lines = BTreeMap::new();
+        let mut calls = BTreeMap::new();
+        let mut work = vec![entry];
+        while let Some(address) = work.pop() {
+            if lines.contains_key(&address) {
+                continue;
+            }
+            let line = match decode(program, address) {
+                Some(line) => line,
+                None => {
+                    let word = program.get(address).copied().unwrap_or(0);
+                    lines.insert(
+                        address,
+                        Line::Data {
+                            address,
+                            values: vec![word],
+                        },
+                    );
+                    continue;
+                }
+            };
+            if let Some(jump) = decode(program, address + 4) {
+                if let Some(call) = call(&line, &jump) {
+                    calls.insert(address, call);
+                    work.push(call.returns_to);
+                    lines.insert(address, line);
+                    lines.insert(address + 4, jump);
+                    continue;
+                }
+            }
+            let (falls, target, _) = flow(&line);
+            if falls {
+                work.push(address + line.len());
+            }
+            work.extend(target);
+            lines.insert(address, line);
+        }
+
+        let mut info = Info {
+            entry,
+            lines,
+            calls,
+            frame: None,
+            params: 0,
+            returns: false,
+            slots: BTreeSet::new(),
+        };
+        info.frame = info.find_frame();
+        if let Some(frame) = info.frame {
+            info.find_slots(frame);
+        }
+        info
+    }
+
+    fn is_call_jump(&self, address: usize) -> bool {
+        address >= 4 && self.calls.contains_key(&(address - 4))
+    }
+
+    /// Length of the `ARB #-frame` and return jump sequence at `address`, if there is one.
+    fn return_at(&self, address: usize) -> Option<usize> {
+        let frame = self.frame?;
+        match self.lines.get(&address)? {
+            Line::Instruction {
+                op: Op::Arb,
+                params,
+                ..
+            } if params[0] == Parameter::ImmediateMode(-frame) => self
+                .lines
+                .get(&(address + 2))
+                .filter(|line| is_return_jump(line))
+                .map(|_| 5),
+            _ => None,
+        }
+    }
+
+    fn is_idiom(&self, address: usize) -> bool {
+        let frame_setup = address == self.entry && self.frame.is_some();
+        let returning = self.return_at(address).is_some()
+            || (address >= 2 && self.return_at(address - 2).is_some());
+        frame_setup || returning || self.calls.contains_key(&address) || self.is_call_jump(address)
+    }
+
+    fn find_frame(&self) -> Option<i64> {
+        let frame = match self.lines.get(&self.entry)? {
+            Line::Instruction {
+                op: Op::Arb,
+                params,
+                ..
+            } => match params[0] {
+                Parameter::ImmediateMode(n) if n > 0 => n,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        for (&address, line) in &self.lines {
+            match line {
+                Line::Instruction {
+                    op: Op::Arb,
+                    params,
+                    ..
+                } if address != self.entry => {
+                    let returns = self.lines.get(&(address + 2)).is_some_and(is_return_jump);
+                    if params[0] != Parameter::ImmediateMode(-frame) || !returns {
+                        return None;
+                    }
+                }
+                _ => {}
+            }
+            // Looping back to the entry would set up the frame again.
+            if !self.is_call_jump(address) && flow(line).1 == Some(self.entry) {
+                return None;
+            }
+        }
+        Some(frame)
+    }
+
+    fn find_slots(&mut self, frame: i64) {
+        let mut first_read = BTreeMap::new();
+        for (&address, line) in &self.lines {
+            if self.is_idiom(address) {
+                continue;
+            }
+            let (reads, write) = operands(line);
+            for (param, read) in reads
+                .iter()
+                .map(|p| (p, true))
+                .chain(write.iter().map(|p| (p, false)))
+            {
+                if let Parameter::RelativeMode(offset) = param {
+                    let slot = offset + frame;
+                    first_read.entry(slot).or_insert(read);
+                    if slot == 1 && !read {
+                        self.returns = true;
+                    }
+                }
+            }
+        }
+        self.params = first_read
+            .iter()
+            .filter(|(slot, read)| **read && (1..frame).contains(*slot))
+            .map(|(slot, _)| *slot as usize)
+            .max()
+            .unwrap_or(0);
+        self.slots = first_read.keys().copied().collect();
+    }
+
+    /// The name of the relative base cell at `offset`.
+    fn slot_name(&self, offset: i64) -> String {
+        let frame = match self.frame {
+            Some(frame) => frame,
+            None => return format!("mem[rb{:+}]", offset),
+        };
+        let slot = offset + frame;
+        let params = self.params as i64;
+        if slot <= 0 || slot == frame {
+            format!("mem[rb{:+}]", offset)
+        } else if slot <= params {
+            format!("a{}", slot - 1)
+        } else if slot < frame {
+            format!("l{}", slot - params - 1)
+        } else {
+            format!("out{}", slot - frame - 1)
+        }
+    }
+}
+
+/// Where `break` and `continue` go in the loop being lifted.
+#[derive(Debug, Clone, Copy, Default)]
+struct Context {
+    continue_to: Option<usize>,
+    break_to: Option<usize>,
+}
+
+struct Lifter<'a> {
+    info: &'a Info,
+    functions: &'a BTreeMap<usize, Info>,
+    leaders: &'a BTreeSet<usize>,
+    temporaries: &'a BTreeSet<usize>,
+    /// Values of temporaries computed by the instruction just lifted.
+    pending: HashMap<usize, Expr>,
+}
+
+impl Lifter<'_> {
+    fn read(&self, param: Parameter) -> Expr {
+        match param {
+            Parameter::ImmediateMode(value) => Expr::Const(value),
+            Parameter::PositionMode(x) => {
+                let pending = if x >= 0 {
+                    self.pending.get(&(x as usize)).cloned()
+                } else {
+                    None
+                };
+                pending.unwrap_or_else(|| Expr::Var(format!("mem[{}]", x)))
+            }
+            Parameter::RelativeMode(offset) => Expr::Var(self.info.slot_name(offset)),
+        }
+    }
+
+    fn target(&self, param: Parameter) -> String {
+        match param {
+            Parameter::PositionMode(x) | Parameter::ImmediateMode(x) => format!("mem[{}]", x),
+            Parameter::RelativeMode(offset) => self.info.slot_name(offset),
+        }
+    }
+
+    /// The first instruction in `from..end`. An instruction overlapping the end of a
+    /// region can leave `from` past it.
+    fn next(&self, from: usize, end: usize) -> Option<usize> {
+        if from >= end {
+            return None;
+        }
+        self.info.lines.range(from..end).next().map(|(a, _)| *a)
+    }
+
+    /// Keep a label for `address` if anything might jump there.
+    fn nop(&self, address: usize, out: &mut Vec<Stmt>) {
+        if self.leaders.contains(&address) {
+            out.push(Stmt {
+                address,
+                kind: Kind::Nop,
+            });
+        }
+    }
+
+    /// The furthest jump back to `header` before `end`.
+    fn back_edge(&self, header: usize, end: usize) -> Option<usize> {
+        self.info
+            .lines
+            .range(header..end)
+            .rev()
+            .find(|(a, line)| !self.info.is_call_jump(**a) && flow(line).1 == Some(header))
+            .map(|(a, _)| *a)
+    }
+
+    /// Lift the instructions in `start..end`. Control leaving the end of the range
+    /// carries on after whatever statement contains it.
+    fn region(
+        &mut self,
+        start: usize,
+        end: usize,
+        context: Context,
+        header: Option<usize>,
+    ) -> Vec<Stmt> {
+        let mut out = Vec::new();
+        let mut cursor = start;
+        while let Some(address) = self.next(cursor, end) {
+            if header != Some(address) {
+                if let Some(back) = self.back_edge(address, end) {
+                    cursor = self.lift_loop(address, back, &mut out);
+                    continue;
+                }
+            }
+            cursor = self.statement(address, end, context, &mut out);
+        }
+        out
+    }
+
+    fn lift_loop(&mut self, header: usize, back: usize, out: &mut Vec<Stmt>) -> usize {
+        let after = back + self.info.lines[&back].len();
+        let context = Context {
+            continue_to: Some(header),
+            break_to: Some(after),
+        };
+        let mut body = self.region(header, after, context, Some(header));
+        if flow(&self.info.lines[&back]).0 {
+            // A conditional jump back, leave the loop when it isn't taken.
+            let stop = Stmt {
+                address: back,
+                kind: Kind::Break,
+            };
+            match body.pop() {
+                Some(Stmt {
+                    address,
+                    kind: Kind::If { cond, then, .. },
+                }) if then.len() == 1 && then[0].kind == Kind::Continue => body.push(Stmt {
+                    address,
+                    kind: Kind::If {
+                        cond: cond.negate(),
+                        then: vec![stop],
+                        otherwise: Vec::new(),
+                    },
+                }),
+                last => body.extend(last.into_iter().chain(Some(stop))),
+            }
+        }
+        out.push(Stmt {
+            address: header,
+            kind: Kind::Loop(body),
+        });
+        after
+    }
+
+    /// Lift the instruction at `address`, returning where to carry on.
+    fn statement(
+        &mut self,
+        address: usize,
+        end: usize,
+        context: Context,
+        out: &mut Vec<Stmt>,
+    ) -> usize {
+        let info = self.info;
+        if let Some(call) = info.calls.get(&address) {
+            self.call(address, *call, out);
+            return address + 7;
+        }
+        if let Some(len) = info.return_at(address) {
+            let value = if info.returns {
+                Some(Expr::Var(info.slot_name(1 - info.frame.unwrap_or(0))))
+            } else {
+                None
+            };
+            out.push(Stmt {
+                address,
+                kind: Kind::Return(value),
+            });
+            return address + len;
+        }
+        if address == info.entry && info.frame.is_some() {
+            self.nop(address, out);
+            return address + 2;
+        }
+
+        let line = &info.lines[&address];
+        let (op, params) = match line {
+            Line::Instruction { op, params, .. } => (*op, params),
+            Line::Data { values, .. } => {
+                out.push(Stmt {
+                    address,
+                    kind: Kind::Unknown(values[0]),
+                });
+                return address + 1;
+            }
+        };
+        let kind = match op {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => {
+                let value = Expr::binary(
+                    BinOp::from_op(op).unwrap(),
+                    self.read(params[0]),
+                    self.read(params[1]),
+                );
+                match fixed_write(line).filter(|x| self.temporaries.contains(x)) {
+                    Some(x) => {
+                        self.pending.insert(x, value);
+                        Kind::Nop
+                    }
+                    None => {
+                        let target = self.target(params[2]);
+                        if value == Expr::Var(target.clone()) {
+                            Kind::Nop
+                        } else {
+                            Kind::Assign(target, value)
+                        }
+                    }
+                }
+            }
+            Op::In => Kind::Input(self.target(params[0])),
+            Op::Out => Kind::Output(self.read(params[0])),
+            Op::Arb => Kind::Arb(self.read(params[0])),
+            Op::Hlt => Kind::Halt,
+            Op::Jnz | Op::Jz => return self.jump(address, op, params, end, context, out),
+        };
+        if kind == Kind::Nop {
+            self.nop(address, out);
+        } else {
+            out.push(Stmt { address, kind });
+        }
+        address + line.len()
+    }
+
+    fn call(&mut self, address: usize, call: Call, out: &mut Vec<Stmt>) {
+        let callee = &self.functions[&call.function];
+        // Arguments go in the slots after the callee's return address.
+        let slots: Vec<String> = (1..=callee.params as i64)
+            .map(|offset| self.info.slot_name(offset))
+            .collect();
+        // Fold in the writes setting them up, the call then starts at the first of them.
+        // Nothing can jump between them, or to the call past them.
+        let mut start = address;
+        let mut values = HashMap::new();
+        while !self.leaders.contains(&start) {
+            match out.last() {
+                Some(Stmt {
+                    address,
+                    kind: Kind::Assign(target, value),
+                }) if slots.contains(target)
+                    && !values.contains_key(target)
+                    && !slots.iter().any(|slot| value.mentions(slot)) =>
+                {
+                    values.insert(target.clone(), value.clone());
+                    start = *address;
+                    out.pop();
+                }
+                _ => break,
+            }
+        }
+        let args = slots
+            .iter()
+            .map(|slot| {
+                values
+                    .remove(slot)
+                    .unwrap_or_else(|| Expr::Var(slot.clone()))
+            })
+            .collect();
+        let target = if callee.returns {
+            Some(self.info.slot_name(1))
+        } else {
+            None
+        };
+        out.push(Stmt {
+            address: start,
+            kind: Kind::Call {
+                target,
+                function: call.function,
+                args,
+            },
+        });
+    }
+
+    fn jump(
+        &mut self,
+        address: usize,
+        op: Op,
+        params: &[Parameter],
+        end: usize,
+        context: Context,
+        out: &mut Vec<Stmt>,
+    ) -> usize {
+        let next = address + 3;
+        let test = self.read(params[0]).truth();
+        let cond = if op == Op::Jnz { test } else { test.negate() };
+        if cond == Expr::Const(0) {
+            self.nop(address, out);
+            return next;
+        }
+        let always = matches!(cond, Expr::Const(_));
+        let push = |kind: Kind, out: &mut Vec<Stmt>| {
+            let stmt = Stmt { address, kind };
+            out.push(if always {
+                stmt
+            } else {
+                Stmt {
+                    address,
+                    kind: Kind::If {
+                        cond: cond.clone(),
+                        then: vec![stmt],
+                        otherwise: Vec::new(),
+                    },
+                }
+            });
+        };
+
+        let target = match params[1] {
+            Parameter::ImmediateMode(target) if target >= 0 => target as usize,
+            param => {
+                push(Kind::Jump(self.read(param)), out);
+                return next;
+            }
+        };
+        if always && Some(target) == context.continue_to && Some(next) == context.break_to {
+            // The jump back closing the loop.
+            self.nop(address, out);
+        } else if Some(target) == context.continue_to {
+            push(Kind::Continue, out);
+        } else if Some(target) == context.break_to {
+            push(Kind::Break, out);
+        } else if target == end && self.next(next, end).is_none() {
+            // Carries on where the enclosing statement does anyway.
+            self.nop(address, out);
+        } else if !always && next <= target && target <= end {
+            return self.lift_if(address, cond, target, end, context, out);
+        } else {
+            push(Kind::Goto(target), out);
+        }
+        next
+    }
+
+    /// Lift a conditional jump forwards over `address + 3..target` to an `if`, with an
+    /// `else` if the skipped code ends by jumping over more.
+    fn lift_if(
+        &mut self,
+        address: usize,
+        cond: Expr,
+        target: usize,
+        end: usize,
+        context: Context,
+        out: &mut Vec<Stmt>,
+    ) -> usize {
+        let next = address + 3;
+        let otherwise_end =
+            self.info
+                .lines
+                .range(next..target)
+                .next_back()
+                .and_then(|(&last, line)| match flow(line) {
+                    (false, Some(join), false)
+                        if target < join
+                            && join <= end
+                            && !self.info.is_call_jump(last)
+                            && !self.leaders.contains(&last)
+                            && Some(join) != context.break_to
+                            && Some(join) != context.continue_to =>
+                    {
+                        Some((last, join))
+                    }
+                    _ => None,
+                });
+
+        let (then, otherwise, resume) = match otherwise_end {
+            Some((last, join)) => (
+                self.region(next, last, context, None),
+                self.region(target, join, context, None),
+                join,
+            ),
+            None => (self.region(next, target, context, None), Vec::new(), target),
+        };
+        let kind = if then.is_empty() {
+            Kind::If {
+                cond,
+                then: otherwise,
+                otherwise: then,
+            }
+        } else {
+            Kind::If {
+                cond: cond.negate(),
+                then,
+                otherwise,
+            }
+        };
+        out.push(Stmt { address, kind });
+        resume
+    }
+}
+
+/// Addresses only ever read straight after being computed, e.g. the flag a comparison
+/// leaves for the next jump to test. Their values are folded into the reader.
+fn temporaries(functions: &BTreeMap<usize, Info>, leaders: &BTreeSet<usize>) -> BTreeSet<usize> {
+    let mut code = BTreeSet::new();
+    let mut written = BTreeMap::new();
+    let mut read = BTreeMap::new();
+    for info in functions.values() {
+        for (&address, line) in &info.lines {
+            code.extend(address..address + line.len());
+            let (reads, write) = operands(line);
+            if let Some(Parameter::PositionMode(x) | Parameter::ImmediateMode(x)) = write {
+                let arithmetic = fixed_write(line).is_some();
+                *written.entry(x).or_insert(true) &= arithmetic;
+            }
+            for param in reads {
+                if let Parameter::PositionMode(x) = *param {
+                    let fresh = !leaders.contains(&address)
+                        && info.lines.range(..address).next_back().is_some_and(
+                            |(&previous, line)| {
+                                previous + line.len() == address
+                                    && fixed_write(line) == Some(x as usize)
+                            },
+                        );
+                    *read.entry(x).or_insert(true) &= fresh;
+                }
+            }
+        }
+    }
+    read.into_iter()
+        .filter(|(x, fresh)| *fresh && *x >= 0 && written.get(x) == Some(&true))
+        .map(|(x, _)| x as usize)
+        .filter(|x| !code.contains(x))
+        .collect()
+}
+
+/// Lift a program to structured pseudo-code.
+///
+/// Functions are found through calls that push a return address to `[rb+0]` and jump,
+/// and are expected to set up a frame with `ARB #n` on entry and leave through
+/// `ARB #-n` and a jump to the return address. Frame slots are named `a0`, `a1`, ...
+/// for arguments, `l0`, `l1`, ... for locals, and `out0`, `out1`, ... for the arguments
+/// of calls being made, the first of which is also where a result comes back.
+/// Anything that can't be structured is left as `goto`s.
+pub fn decompile(program: &[i64]) -> Decompiled {
+    let mut functions = BTreeMap::new();
+    let mut work = vec![0];
+    while let Some(entry) = work.pop() {
+        if functions.contains_key(&entry) {
+            continue;
+        }
+        let info = Info::new(program, entry);
+        work.extend(info.calls.values().map(|call| call.function));
+        functions.insert(entry, info);
+    }
+
+    let mut leaders = BTreeSet::new();
+    for info in functions.values() {
+        leaders.insert(info.entry);
+        leaders.extend(info.calls.values().map(|call| call.returns_to));
+        for (&address, line) in &info.lines {
+            if !info.is_call_jump(address) {
+                leaders.extend(flow(line).1);
+            }
+        }
+    }
+    let temporaries = temporaries(&functions, &leaders);
+
+    let functions = functions
+        .values()
+        .map(|info| {
+            let mut lifter = Lifter {
+                info,
+                functions: &functions,
+                leaders: &leaders,
+                temporaries: &temporaries,
+                pending: HashMap::new(),
+            };
+            let start = *info.lines.keys().next().unwrap_or(&info.entry);
+            let end = info
+                .lines
+                .iter()
+                .map(|(a, l)| a + l.len())
+                .max()
+                .unwrap_or(start);
+            let mut body = Vec::new();
+            if start != info.entry {
+                body.push(Stmt {
+                    address: start,
+                    kind: Kind::Goto(info.entry),
+                });
+            }
+            body.extend(lifter.region(start, end, Context::default(), None));
+
+            let frame = info.frame.unwrap_or(0);
+            Function {
+                entry: info.entry,
+                params: (0..info.params).map(|i| format!("a{}", i)).collect(),
+                locals: info
+                    .slots
+                    .iter()
+                    .filter(|slot| **slot > info.params as i64 && **slot != frame)
+                    .map(|slot| info.slot_name(slot - frame))
+                    .collect(),
+                body,
+            }
+        })
+        .collect();
+    Decompiled { functions }
+}
+
+fn gotos(stmts: &[Stmt], labels: &mut BTreeSet<usize>) {
+    for stmt in stmts {
+        match &stmt.kind {
+            Kind::Goto(target) => {
+                labels.insert(*target);
+            }
+            Kind::If {
+                then, otherwise, ..
+            } => {
+                gotos(then, labels);
+                gotos(otherwise, labels);
+            }
+            Kind::Loop(body) => gotos(body, labels),
+            _ => {}
+        }
+    }
+}
+
+/// Statements that fit on the same line as the `if` guarding them.
+fn jump_line(kind: &Kind) -> Option<String> {
+    match kind {
+        Kind::Break => Some("break;".to_string()),
+        Kind::Continue => Some("continue;".to_string()),
+        Kind::Goto(target) => Some(format!("goto L{};", target)),
+        Kind::Jump(target) => Some(format!("goto *{};", target)),
+        _ => None,
+    }
+}
+
+fn write_block(
+    f: &mut fmt::Formatter,
+    stmts: &[Stmt],
+    depth: usize,
+    labels: &mut BTreeSet<usize>,
+) -> fmt::Result {
+    let indent = "    ".repeat(depth);
+    for stmt in stmts {
+        if labels.remove(&stmt.address) {
+            writeln!(f, "{}L{}:", "    ".repeat(depth - 1), stmt.address)?;
+        }
+        if let Some(line) = jump_line(&stmt.kind) {
+            writeln!(f, "{}{}", indent, line)?;
+            continue;
+        }
+        match &stmt.kind {
+            Kind::Assign(target, value) => writeln!(f, "{}{} = {};", indent, target, value)?,
+            Kind::Input(target) => writeln!(f, "{}{} = input();", indent, target)?,
+            Kind::Output(value) => writeln!(f, "{}output({});", indent, value)?,
+            Kind::Arb(value) => writeln!(f, "{}rb += {};", indent, value)?,
+            Kind::Call {
+                target,
+                function,
+                args,
+            } => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                let call = format!("{}({})", function_name(*function), args.join(", "));
+                match target {
+                    Some(target) => writeln!(f, "{}{} = {};", indent, target, call)?,
+                    None => writeln!(f, "{}{};", indent, call)?,
+                }
+            }
+            Kind::Return(None) => writeln!(f, "{}return;", indent)?,
+            Kind::Return(Some(value)) => writeln!(f, "{}return {};", indent, value)?,
+            Kind::Halt => writeln!(f, "{}halt;", indent)?,
+            Kind::If {
+                cond,
+                then,
+                otherwise,
+            } => match (&then[..], otherwise.is_empty()) {
+                ([single], true) if jump_line(&single.kind).is_some() => {
+                    let line = jump_line(&single.kind).unwrap_or_default();
+                    writeln!(f, "{}if ({}) {}", indent, cond, line)?;
+                }
+                _ => {
+                    writeln!(f, "{}if ({}) {{", indent, cond)?;
+                    write_block(f, then, depth + 1, labels)?;
+                    let labeled = |s: &Stmt| s.kind != Kind::Nop || labels.contains(&s.address);
+                    if otherwise.iter().any(labeled) {
+                        writeln!(f, "{}}} else {{", indent)?;
+                        write_block(f, otherwise, depth + 1, labels)?;
+                    }
+                    writeln!(f, "{}}}", indent)?;
+                }
+            },
+            Kind::Loop(body) => {
+                writeln!(f, "{}loop {{", indent)?;
+                write_block(f, body, depth + 1, labels)?;
+                writeln!(f, "{}}}", indent)?;
+            }
+            Kind::Unknown(word) => writeln!(f, "{}unknown({});", indent, word)?,
+            Kind::Nop => {}
+            Kind::Break | Kind::Continue | Kind::Goto(_) | Kind::Jump(_) => unreachable!(),
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "fn {}({}) {{", self.name(), self.params.join(", "))?;
+        if !self.locals.is_empty() {
+            writeln!(f, "    var {};", self.locals.join(", "))?;
+        }
+        let mut labels = BTreeSet::new();
+        gotos(&self.body, &mut labels);
+        write_block(f, &self.body, 1, &mut labels)?;
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Decompiled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_loop() -> anyhow::Result<()> {
+    use crate::intcode::asm::assemble;
+
+    let program = assemble(
+        "
+        loop:   IN -> [value]
+                JZ [value], #done
+                MUL [value], #2 -> [value]
+                OUT [value]
+                JNZ #1, #loop
+        done:   HLT
+        value:  DB 0
+        ",
+    )?;
+    assert_eq!(
+        "fn main() {
+    loop {
+        mem[15] = input();
+        if (mem[15] == 0) break;
+        mem[15] = mem[15] * 2;
+        output(mem[15]);
+    }
+    halt;
+}
+",
+        decompile(&program).to_string()
+    );
+
+    // A loop closed by a conditional jump, testing a flag the comparison leaves.
+    let program = assemble(
+        "
+                IN -> [n]
+        again:  OUT [n]
+                ADD [n], #-1 -> [n]
+                LT #0, [n] -> [flag]
+                JNZ [flag], #again
+                HLT
+        n:      DB 0
+        flag:   DB 0
+        ",
+    )?;
+    assert_eq!(
+        "fn main() {
+    mem[16] = input();
+    loop {
+        output(mem[16]);
+        mem[16] = mem[16] - 1;
+        if (0 >= mem[16]) break;
+    }
+    halt;
+}
+",
+        decompile(&program).to_string()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_day9() -> anyhow::Result<()> {
+    let program = crate::intcode::read_program("input/day9.txt")?;
+    let decompiled = decompile(&program);
+    assert_eq!(
+        vec![0, 920],
+        decompiled
+            .functions
+            .iter()
+            .map(|f| f.entry)
+            .collect::<Vec<_>>()
+    );
+
+    // The boost mode's recursive function, with its stack frame, comparison flag and
+    // return through the stack all lifted.
+    assert_eq!(
+        "fn f920(a0) {
+    var l0, out0;
+    if (a0 >= 3) {
+        out0 = f920(a0 - 1);
+        l0 = out0;
+        out0 = f920(a0 - 3);
+        a0 = out0 + l0;
+    }
+    return a0;
+}
+",
+        decompiled.function(920).unwrap().to_string()
+    );
+
+    let main = decompiled.function(0).unwrap().to_string();
+    assert!(main.contains(
+        "
+L902:
+    mem[rb+1] = f920(27);
+    mem[rb+1] = mem[rb+1] + 53612;
+    output(mem[rb+1]);
+    halt;
+"
+    ));
+    assert!(main.contains("if (mem[1000] == 2) goto L902;"));
+    Ok(())
+}
+
+#[test]
+fn test_overlapping() {
+    // The jump at 0 targets the middle of itself, so instructions overlap.
+    let decompiled = decompile(&[1005, 1105, 1, 0, 21101, 1957]);
+    assert_eq!(
+        "fn main() {\n    loop {\n        if (mem[1105] != 0) goto L1;\n        unknown(0);\n    }\n}\n",
+        decompiled.to_string()
+    );
+}
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+/// Where a machine's input comes from once its queue is empty, see `CPU::set_input`.
+pub trait InputDevice {
+    /// The next value, `None` if there isn't one yet.
+    fn read(&mut self) -> Option<i64>;
+}
+
+/// Where a machine's output goes, see `CPU::set_output`.
+pub trait OutputDevice {
+    fn write(&mut self, value: i64);
+}
+
+impl InputDevice for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl OutputDevice for VecDeque<i64> {
+    fn write(&mut self, value: i64) {
+        self.push_back(value);
+    }
+}
+
+impl OutputDevice for Vec<i64> {
+    fn write(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+/// Lets a device be shared, e.g. one machine's output queue as another's input.
+impl<T: InputDevice> InputDevice for Rc<RefCell<T>> {
+    fn read(&mut self) -> Option<i64> {
+        self.borrow_mut().read()
+    }
+}
+
+impl<T: OutputDevice> OutputDevice for Rc<RefCell<T>> {
+    fn write(&mut self, value: i64) {
+        self.borrow_mut().write(value);
+    }
+}
+
+/// Input produced by a closure.
+pub struct InputFn<F: FnMut() -> Option<i64>>(pub F);
+
+impl<F: FnMut() -> Option<i64>> InputDevice for InputFn<F> {
+    fn read(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+/// Output consumed by a closure.
+pub struct OutputFn<F: FnMut(i64)>(pub F);
+
+impl<F: FnMut(i64)> OutputDevice for OutputFn<F> {
+    fn write(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
+
+/// Reads numbers separated by whitespace or commas, e.g. from a file or stdin.
+pub struct Reader<R: BufRead> {
+    input: R,
+    pending: VecDeque<i64>,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R) -> Reader<R> {
+        Reader {
+            input,
+            pending: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// The first error reading or parsing, if any. Reading stops after it.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+}
+
+impl<R: BufRead> InputDevice for Reader<R> {
+    fn read(&mut self) -> Option<i64> {
+        while self.pending.is_empty() && self.error.is_none() {
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {
+                    for word in line.split(|c: char| c == ',' || c.is_whitespace()) {
+                        if word.is_empty() {
+                            continue;
+                        }
+                        match word.parse() {
+                            Ok(value) => self.pending.push_back(value),
+                            Err(e) => {
+                                self.error = Some(io::Error::new(io::ErrorKind::InvalidData, e));
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Writes one number per line, e.g. to a file or stdout.
+///
+/// Devices can't fail, so the first error is kept for `finish` and nothing more is
+/// written after it.
+pub struct Writer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(out: W) -> Writer<W> {
+        Writer { out, error: None }
+    }
+
+    /// Write `line` followed by a newline, unless an earlier write failed.
+    pub fn line(&mut self, line: impl fmt::Display) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", line) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Get the writer back, along with the first error writing to it.
+    pub fn finish(self) -> io::Result<W> {
+        match self.error {
+            Some(e) => Err(e),
+            None => Ok(self.out),
+        }
+    }
+}
+
+impl<W: Write> OutputDevice for Writer<W> {
+    fn write(&mut self, value: i64) {
+        self.line(value);
+    }
+}
+
+#[test]
+fn test_devices() -> anyhow::Result<()> {
+    use crate::intcode::{Status, CPU};
+
+    // Double every input until a zero.
+    let program = vec![
+        3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1105, 1, 0, 99, 0,
+    ];
+
+    let mut cpu = CPU::new(&program);
+    cpu.set_input(Box::new(Reader::new(&b"1, 2\n3\n"[..])));
+    let outputs = Rc::new(RefCell::new(Vec::new()));
+    cpu.set_output(Box::new(outputs.clone()));
+    assert_eq!(Status::NeedsInput, cpu.step()?);
+    assert_eq!(vec![2, 4, 6], *outputs.borrow());
+
+    // Queued inputs go first, then the device.
+    cpu.add_input(4);
+    let mut count = 0;
+    cpu.set_input(Box::new(InputFn(move || {
+        count += 1;
+        if count < 3 {
+            Some(10 * count)
+        } else {
+            Some(0)
+        }
+    })));
+    let shared = outputs.clone();
+    cpu.set_output(Box::new(OutputFn(move |value| {
+        shared.borrow_mut().push(value)
+    })));
+    assert_eq!(Status::Halted, cpu.step()?);
+    assert_eq!(vec![2, 4, 6, 8, 20, 40], *outputs.borrow());
+
+    // Chain two machines through a shared queue.
+    let pipe = Rc::new(RefCell::new(VecDeque::new()));
+    let mut first = CPU::new(&program);
+    first.set_output(Box::new(pipe.clone()));
+    let mut second = CPU::new(&program);
+    second.set_input(Box::new(pipe.clone()));
+    for i in &[1, 5, 0] {
+        first.add_input(*i);
+    }
+    assert_eq!(Status::Halted, first.step()?);
+    assert_eq!(
+        vec![Ok(4), Ok(20)],
+        second.outputs().take(2).collect::<Vec<_>>()
+    );
+    assert!(pipe.borrow().is_empty());
+
+    let mut writer = Writer::new(Vec::new());
+    writer.write(1);
+    writer.write(-2);
+    assert_eq!(b"1\n-2\n", &writer.finish()?[..]);
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+pub use crate::intcode::op::Op;
+use crate::intcode::{OpCodeMode, Parameter};
+
+/// A decoded line of a listing.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Line {
+    Instruction {
+        address: usize,
+        op: Op,
+        params: Vec<Parameter>,
+    },
+    /// Words that don't decode to an instruction.
+    Data { address: usize, values: Vec<i64> },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+        }
+    }
+
+    /// Number of words this line covers.
+    pub fn len(&self) -> usize {
+        match self {
+            Line::Instruction { params, .. } => params.len() + 1,
+            Line::Data { values, .. } => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::PositionMode(n) => write!(f, "[{}]", n),
+            Parameter::ImmediateMode(n) => write!(f, "#{}", n),
+            Parameter::RelativeMode(n) if *n < 0 => write!(f, "[rb-{}]", -n),
+            Parameter::RelativeMode(n) => write!(f, "[rb+{}]", n),
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction { op, params, .. } => {
+                write!(f, "{}", op.mnemonic())?;
+                let (reads, write) = if op.writes() {
+                    params.split_at(params.len() - 1)
+                } else {
+                    params.split_at(params.len())
+                };
+                for (i, param) in reads.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, param)?;
+                }
+                if let Some(target) = write.first() {
+                    write!(f, " -> {}", target)?;
+                }
+                Ok(())
+            }
+            Line::Data { values, .. } => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "DB {}", values.join(", "))
+            }
+        }
+    }
+}
+
+/// Maximum number of data words grouped on one line.
+const DATA_PER_LINE: usize = 8;
+
+/// Decode the instruction at `address`, if the words there form a valid one.
+pub fn decode(program: &[i64], address: usize) -> Option<Line> {
+    let code = *program.get(address)?;
+    if code < 0 {
+        return None;
+    }
+    let modes: OpCodeMode = code.into();
+    let op = Op::from_opcode(modes.opcode)?;
+
+    // Modes beyond the arity of the instruction should be left at zero.
+    let arity = op.arity();
+    if code / 10i64.pow(2 + arity as u32) != 0 {
+        return None;
+    }
+
+    let params = [modes.p1, modes.p2, modes.p3]
+        .iter()
+        .take(arity)
+        .enumerate()
+        .map(|(i, mode)| {
+            let raw = *program.get(address + 1 + i)?;
+            Parameter::build(*mode, raw)
+        })
+        .collect::<Option<Vec<Parameter>>>()?;
+
+    Some(Line::Instruction {
+        address,
+        op,
+        params,
+    })
+}
+
+/// Linearly sweep a program, decoding instructions and grouping everything else as data.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < program.len() {
+        let line = match decode(program, address) {
+            Some(line) => line,
+            None => match lines.last_mut() {
+                Some(Line::Data { values, .. }) if values.len() < DATA_PER_LINE => {
+                    values.push(program[address]);
+                    address += 1;
+                    continue;
+                }
+                _ => Line::Data {
+                    address,
+                    values: vec![program[address]],
+                },
+            },
+        };
+        address += line.len();
+        lines.push(line);
+    }
+    lines
+}
+
+/// Render a listing with the address of every line.
+pub fn listing(program: &[i64]) -> String {
+    disassemble(program)
+        .iter()
+        .map(|line| format!("{:>5}: {}\n", line.address(), line))
+        .collect()
+}
+
+#[test]
+fn test_disassemble() {
+    let program = vec![1002, 4, 3, 4, 33, 21101, 12, 5, 3, 204, -1, 99];
+    let lines: Vec<String> = disassemble(&program)
+        .iter()
+        .map(|l| l.to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            "MUL [4], #3 -> [4]",
+            "DB 33",
+            "ADD #12, #5 -> [rb+3]",
+            "OUT [rb-1]",
+            "HLT",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_data_regions() {
+    // Unknown opcodes, bad modes, stray high modes and truncated instructions are data.
+    let program = vec![99, 0, 42, 1, 2, 3, 555, 10099, -7, 1101, 1];
+    assert_eq!(
+        "    0: HLT\n    1: DB 0, 42\n    3: ADD [2], [3] -> [555]\n    7: DB 10099, -7, 1101, 1\n",
+        listing(&program)
+    );
+}
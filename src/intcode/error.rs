@@ -0,0 +1,115 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::intcode::limits::Limit;
+
+/// Where the machine was when something went wrong.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Location {
+    pub pc: usize,
+    /// The raw instruction word at `pc`, modes included.
+    pub instruction: i64,
+    pub relative_base: i64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "pc {} (instruction {}, relative base {})",
+            self.pc, self.instruction, self.relative_base
+        )
+    }
+}
+
+/// Everything that can go wrong executing an Intcode program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        opcode: i64,
+        at: Location,
+    },
+    BadParameterMode {
+        mode: u8,
+        at: Location,
+    },
+    /// A parameter that gets written to is in immediate mode.
+    ImmediateWrite {
+        at: Location,
+    },
+    NegativeAddress {
+        address: i64,
+        at: Location,
+    },
+    /// The program wanted input but there was none, and the caller can't provide more.
+    InputExhausted {
+        at: Location,
+    },
+    /// The instruction, or one of its parameters, is past the end of memory.
+    PcOutOfBounds {
+        at: Location,
+    },
+    /// A custom instruction asked to write but has no parameter to write through.
+    NoWriteParameter {
+        at: Location,
+    },
+    /// An addition, multiplication or relative address went past the range of an `i64`.
+    Overflow {
+        at: Location,
+    },
+    /// Running the instruction would go over one of the machine's `Limits`.
+    LimitExceeded {
+        limit: Limit,
+        at: Location,
+    },
+}
+
+impl IntcodeError {
+    pub fn location(&self) -> Location {
+        match self {
+            IntcodeError::UnknownOpcode { at, .. }
+            | IntcodeError::BadParameterMode { at, .. }
+            | IntcodeError::ImmediateWrite { at }
+            | IntcodeError::NegativeAddress { at, .. }
+            | IntcodeError::InputExhausted { at }
+            | IntcodeError::PcOutOfBounds { at }
+            | IntcodeError::NoWriteParameter { at }
+            | IntcodeError::Overflow { at }
+            | IntcodeError::LimitExceeded { at, .. } => *at,
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeError::UnknownOpcode { opcode, at } => {
+                write!(f, "unknown opcode {} at {}", opcode, at)
+            }
+            IntcodeError::BadParameterMode { mode, at } => {
+                write!(f, "unknown mode {} at {}", mode, at)
+            }
+            IntcodeError::ImmediateWrite { at } => {
+                write!(f, "write to an immediate mode parameter at {}", at)
+            }
+            IntcodeError::NegativeAddress { address, at } => {
+                write!(f, "negative address {} at {}", address, at)
+            }
+            IntcodeError::InputExhausted { at } => write!(f, "ran out of inputs at {}", at),
+            IntcodeError::PcOutOfBounds { at } => write!(f, "ran off the end of memory at {}", at),
+            IntcodeError::NoWriteParameter { at } => {
+                write!(
+                    f,
+                    "write from an instruction without a write parameter at {}",
+                    at
+                )
+            }
+            IntcodeError::Overflow { at } => write!(f, "arithmetic overflow at {}", at),
+            IntcodeError::LimitExceeded { limit, at } => {
+                write!(f, "exceeded the {} at {}", limit, at)
+            }
+        }
+    }
+}
+
+impl Error for IntcodeError {}
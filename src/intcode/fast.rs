@@ -0,0 +1,411 @@
+use crate::intcode::error::{IntcodeError, Location};
+use crate::intcode::limits::Limits;
+use crate::intcode::op::Op;
+use crate::intcode::{to_address, OpCodeMode, Parameter, Status, CPU};
+
+/// Only instructions below this address are cached, anything above runs on the
+/// reference interpreter.
+const MAX_CACHED: usize = 1 << 20;
+
+/// A parameter with its mode already resolved.
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Position(usize),
+    Immediate(i64),
+    Relative(i64),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    op: Op,
+    operands: [Operand; 3],
+}
+
+impl Decoded {
+    fn len(&self) -> usize {
+        self.op.arity() + 1
+    }
+}
+
+/// An execution engine that decodes each instruction once and runs the cached form.
+///
+/// Writes landing inside a decoded instruction drop it from the cache, so
+/// self-modifying programs behave exactly like they do on `CPU::step`. Anything
+/// unusual, errors included, is handed to `CPU::execute`. The same goes for every
+/// instruction while a tracer or a custom instruction set is set.
+pub struct FastCPU {
+    cpu: CPU,
+    cache: Vec<Option<Decoded>>,
+    invalidations: usize,
+}
+
+impl FastCPU {
+    pub fn new(memory: &[i64]) -> FastCPU {
+        FastCPU::from_cpu(CPU::new(memory))
+    }
+
+    pub fn from_cpu(cpu: CPU) -> FastCPU {
+        FastCPU {
+            cpu,
+            cache: Vec::new(),
+            invalidations: 0,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn into_cpu(self) -> CPU {
+        self.cpu
+    }
+
+    pub fn add_input(&mut self, input: i64) {
+        self.cpu.add_input(input);
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.cpu.set_limits(limits);
+    }
+
+    pub fn get_memory(&self, position: usize) -> i64 {
+        self.cpu.get_memory(position)
+    }
+
+    pub fn set_memory(&mut self, position: usize, value: i64) {
+        self.cpu.set_memory(position, value);
+        self.invalidate(position);
+    }
+
+    /// How many decoded instructions were thrown away because they were written to.
+    pub fn invalidations(&self) -> usize {
+        self.invalidations
+    }
+
+    /// Drop every cached instruction covering `address`.
+    fn invalidate(&mut self, address: usize) {
+        if self.cache.is_empty() || address >= self.cache.len() + 3 {
+            return;
+        }
+        let end = address.min(self.cache.len() - 1);
+        for start in address.saturating_sub(3)..=end {
+            if let Some(decoded) = self.cache[start] {
+                if start + decoded.len() > address {
+                    self.cache[start] = None;
+                    self.invalidations += 1;
+                }
+            }
+        }
+    }
+
+    /// Decode the instruction at `pc`, `None` if it would fail or is better left to `CPU`.
+    fn decode(&self, pc: usize) -> Option<Decoded> {
+        let code = self.cpu.mem.get(pc)?;
+        let modes: OpCodeMode = code.into();
+        let op = Op::from_opcode(modes.opcode)?;
+        let modes = [modes.p1, modes.p2, modes.p3];
+
+        let mut operands = [Operand::Immediate(0); 3];
+        for (i, operand) in operands.iter_mut().enumerate().take(op.arity()) {
+            let raw = self.cpu.mem.get(pc + 1 + i)?;
+            *operand = match Parameter::build(modes[i], raw)? {
+                Parameter::PositionMode(n) if n < 0 => return None,
+                Parameter::PositionMode(n) => Operand::Position(n as usize),
+                Parameter::ImmediateMode(_) if op.writes() && i + 1 == op.arity() => {
+                    // Lenient mode writes through it like position mode, strict mode fails.
+                    if self.cpu.strict || raw < 0 {
+                        return None;
+                    }
+                    Operand::Position(raw as usize)
+                }
+                Parameter::ImmediateMode(n) => Operand::Immediate(n),
+                Parameter::RelativeMode(n) => Operand::Relative(n),
+            };
+        }
+        Some(Decoded { op, operands })
+    }
+
+    /// The address `offset` from the relative base, `None` if it's out of range.
+    fn relative(&self, offset: i64) -> Option<usize> {
+        let address = self.cpu.relative_base.checked_add(offset)?;
+        to_address(address, Location::default()).ok()
+    }
+
+    fn read(&self, operand: Operand) -> Option<i64> {
+        let address = match operand {
+            Operand::Position(address) => address,
+            Operand::Immediate(value) => return Some(value),
+            Operand::Relative(offset) => self.relative(offset)?,
+        };
+        Some(self.cpu.mem.get(address).unwrap_or(0))
+    }
+
+    fn target(&self, operand: Operand) -> Option<usize> {
+        match operand {
+            Operand::Position(address) => Some(address),
+            Operand::Immediate(_) => None,
+            Operand::Relative(offset) => self.relative(offset),
+        }
+    }
+
+    /// Run one instruction on the reference interpreter.
+    fn slow(&mut self) -> Result<Option<Status<i64>>, IntcodeError> {
+        let writes = self
+            .cpu
+            .mem
+            .get(self.cpu.pc)
+            .and_then(|code| Op::from_opcode((code % 100) as u8))
+            .is_some_and(|op| op.writes());
+        let status = self.cpu.execute()?;
+        if writes && status.is_none() {
+            // Rare enough that working out what was written isn't worth it.
+            self.invalidations += self.cache.iter().flatten().count();
+            self.cache.clear();
+        }
+        Ok(status)
+    }
+
+    fn cached(&mut self, pc: usize) -> Option<Decoded> {
+        if pc >= MAX_CACHED
+            || self.cpu.tracer.is_some()
+            || self.cpu.isa.is_some()
+            || self.cpu.history.is_some()
+        {
+            return None;
+        }
+        if let Some(decoded) = self.cache.get(pc).copied().flatten() {
+            return Some(decoded);
+        }
+        let decoded = self.decode(pc)?;
+        if self.cache.len() <= pc {
+            self.cache.resize(pc + 1, None);
+        }
+        self.cache[pc] = Some(decoded);
+        Some(decoded)
+    }
+
+    /// Run until the next output, until input is needed or until the program halts,
+    /// the same as `CPU::step`.
+    pub fn step(&mut self) -> Result<Status<i64>, IntcodeError> {
+        loop {
+            if let Some(status) = self.execute()? {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Execute a single instruction, see `CPU::execute`.
+    pub fn execute(&mut self) -> Result<Option<Status<i64>>, IntcodeError> {
+        let pc = self.cpu.pc;
+        let decoded = match self.cached(pc) {
+            Some(decoded) => decoded,
+            None => return self.slow(),
+        };
+        // Going over a limit is an error, which the reference interpreter reports.
+        let limits = self.cpu.limits;
+        if limits.check_instruction(&self.cpu.usage).is_some() {
+            return self.slow();
+        }
+
+        let [p1, p2, p3] = decoded.operands;
+        let mut next = pc + decoded.len();
+        let mut status = None;
+        let mut write = None;
+        match decoded.op {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => {
+                let (a, b, target) = match (self.read(p1), self.read(p2), self.target(p3)) {
+                    (Some(a), Some(b), Some(target)) if limits.check_write(target).is_none() => {
+                        (a, b, target)
+                    }
+                    _ => return self.slow(),
+                };
+                let value = match decoded.op {
+                    Op::Add => a.checked_add(b),
+                    Op::Mul => a.checked_mul(b),
+                    Op::Lt => Some((a < b) as i64),
+                    _ => Some((a == b) as i64),
+                };
+                let value = match value {
+                    Some(value) => value,
+                    None => return self.slow(),
+                };
+                write = Some((target, value));
+            }
+            Op::In => {
+                let target = match self.target(p1) {
+                    Some(target) if limits.check_write(target).is_none() => target,
+                    _ => return self.slow(),
+                };
+                match self.cpu.next_input() {
+                    Some(input) => write = Some((target, input)),
+                    None => return Ok(Some(Status::NeedsInput)),
+                }
+            }
+            Op::Out => {
+                let value = match self.read(p1) {
+                    Some(value) if limits.check_output(&self.cpu.usage).is_none() => value,
+                    _ => return self.slow(),
+                };
+                self.cpu.usage.outputs += 1;
+                match self.cpu.output.as_mut() {
+                    Some(device) => device.write(value),
+                    None => status = Some(Status::Ready(value)),
+                }
+            }
+            Op::Jnz | Op::Jz => {
+                let (test, target) = match (self.read(p1), self.read(p2)) {
+                    (Some(test), Some(target)) => (test, target),
+                    _ => return self.slow(),
+                };
+                if (test != 0) == (decoded.op == Op::Jnz) {
+                    next = match to_address(target, Location::default()) {
+                        Ok(target) => target,
+                        Err(_) => return self.slow(),
+                    };
+                }
+            }
+            Op::Arb => match self.read(p1) {
+                Some(offset) => match self.cpu.relative_base.checked_add(offset) {
+                    Some(base) => self.cpu.relative_base = base,
+                    None => return self.slow(),
+                },
+                None => return self.slow(),
+            },
+            Op::Hlt => {
+                self.cpu.usage.instructions += 1;
+                return Ok(Some(Status::Halted));
+            }
+        }
+
+        if let Some((address, value)) = write {
+            self.cpu.mem.set(address, value);
+            self.invalidate(address);
+        }
+        self.cpu.pc = next;
+        self.cpu.usage.instructions += 1;
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+fn run_both(program: &[i64], inputs: &[i64]) -> Result<Vec<i64>, IntcodeError> {
+    let mut cpu = CPU::new(program);
+    let mut fast = FastCPU::new(program);
+    for input in inputs {
+        cpu.add_input(*input);
+        fast.add_input(*input);
+    }
+
+    let mut outputs = Vec::new();
+    loop {
+        let status = cpu.step();
+        assert_eq!(status, fast.step());
+        match status? {
+            Status::Ready(value) => outputs.push(value),
+            _ => break,
+        }
+    }
+    assert_eq!(cpu.pc(), fast.cpu().pc());
+    assert_eq!(cpu.memory().to_vec(), fast.cpu().memory().to_vec());
+    Ok(outputs)
+}
+
+#[test]
+fn test_self_modifying() -> Result<(), IntcodeError> {
+    // Each pass bumps the opcode of the first instruction, turning ADD into MUL.
+    let program = crate::intcode::asm::assemble(
+        "
+        loop:   ADD [x], #3 -> [x]
+                OUT [x]
+                ADD [loop], #1 -> [loop]
+                LT [loop], #1003 -> [flag]
+                JNZ [flag], #loop
+                HLT
+        x:      DB 1
+        flag:   DB 0
+        ",
+    )
+    .unwrap();
+    assert_eq!(vec![4, 12], run_both(&program, &[])?);
+
+    let mut fast = FastCPU::new(&program);
+    while let Status::Ready(_) = fast.step()? {}
+    assert_eq!(2, fast.invalidations());
+    Ok(())
+}
+
+#[test]
+fn test_errors() {
+    for program in &[
+        vec![1101, 1, 1, -3, 99],
+        vec![109, -5, 2201, 4, 0, 0, 99],
+        vec![1105, 1, 3, 142],
+        vec![301, 0, 0, 0, 99],
+        vec![1, 0, 0],
+        vec![203, -1, 99],
+        vec![1102, i64::MAX, 2, 0, 99],
+        vec![109, i64::MAX, 109, 1, 99],
+        vec![109, i64::MAX, 2201, 1, 0, 0, 99],
+    ] {
+        assert!(run_both(program, &[]).is_err());
+    }
+
+    let strict = || {
+        let mut cpu = CPU::new(&[11101, 2, 3, 5, 99, 0]);
+        cpu.set_strict(true);
+        cpu
+    };
+    assert_eq!(strict().step(), FastCPU::from_cpu(strict()).step());
+}
+
+#[test]
+fn test_differential() -> anyhow::Result<()> {
+    use crate::intcode::read_program;
+
+    let day2 = read_program("input/day2.txt")?;
+    for noun in 0..100 {
+        let mut program = day2.clone();
+        program[1] = noun;
+        program[2] = 99 - noun;
+        run_both(&program, &[])?;
+    }
+
+    let day5 = read_program("input/day5.txt")?;
+    assert_eq!(Some(&5044655), run_both(&day5, &[1])?.last());
+    assert_eq!(vec![7408802], run_both(&day5, &[5])?);
+
+    let day9 = read_program("input/day9.txt")?;
+    assert_eq!(vec![2377080455], run_both(&day9, &[1])?);
+    assert_eq!(vec![74917], run_both(&day9, &[2])?);
+
+    // Run day 7's feedback loop on both engines in lockstep.
+    let day7 = read_program("input/day7.txt")?;
+    for phases in &[[9, 8, 7, 6, 5], [5, 7, 9, 6, 8], [6, 5, 8, 9, 7]] {
+        let mut amps: Vec<(CPU, FastCPU)> = phases
+            .iter()
+            .map(|phase| {
+                let mut cpu = CPU::new(&day7);
+                let mut fast = FastCPU::new(&day7);
+                cpu.add_input(*phase);
+                fast.add_input(*phase);
+                (cpu, fast)
+            })
+            .collect();
+
+        let mut signal = 0;
+        'feedback: loop {
+            for (cpu, fast) in amps.iter_mut() {
+                cpu.add_input(signal);
+                fast.add_input(signal);
+                let status = cpu.step()?;
+                assert_eq!(status, fast.step()?);
+                match status {
+                    Status::Ready(value) => signal = value,
+                    _ => break 'feedback,
+                }
+            }
+        }
+        assert!(signal > 0);
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+/// How many instructions the undo log keeps unless told otherwise, see
+/// `CPU::set_history_limit`.
+pub const DEFAULT_LIMIT: usize = 1 << 20;
+
+/// Everything one executed instruction changed, enough to take it back with `CPU::undo`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Undo {
+    pub pc: usize,
+    pub relative_base: i64,
+    pub write: Option<Write>,
+    /// The value the instruction consumed, which goes back on the input queue.
+    pub input: Option<i64>,
+    /// The value the instruction output. It can't be taken back from a device.
+    pub output: Option<i64>,
+}
+
+/// A memory write along with what it overwrote.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Write {
+    pub address: usize,
+    pub old: i64,
+    /// Memory size before the write, which may have grown it.
+    pub len: usize,
+}
+
+#[test]
+fn test_undo() {
+    use crate::intcode::{Status, CPU};
+
+    let program = [3, 9, 109, 4, 22201, 5, 5, 1000, 104, 0];
+    let mut cpu = CPU::new(&program);
+    cpu.record_history(true);
+    cpu.add_input(21);
+    assert_eq!(Ok(None), cpu.execute());
+    assert_eq!(Ok(None), cpu.execute());
+    assert_eq!(Ok(None), cpu.execute());
+    assert_eq!(Ok(Some(Status::Ready(21))), cpu.execute());
+    assert_eq!(1005, cpu.memory().len());
+    assert_eq!(42, cpu.get_memory(1004));
+    assert_eq!(
+        Some(&Undo {
+            pc: 4,
+            relative_base: 4,
+            write: Some(Write {
+                address: 1004,
+                old: 0,
+                len: 10,
+            }),
+            input: None,
+            output: None,
+        }),
+        cpu.history().get(2)
+    );
+
+    assert_eq!(Some(Some(21)), cpu.undo().map(|undo| undo.output));
+    cpu.undo();
+    assert_eq!(10, cpu.memory().len());
+    assert_eq!(4, cpu.pc());
+    cpu.undo();
+    cpu.undo();
+    assert_eq!(None, cpu.undo());
+    assert_eq!(program.to_vec(), cpu.memory().to_vec());
+    assert_eq!(0, cpu.usage().instructions);
+    assert_eq!(0, cpu.usage().outputs);
+
+    // Replaying reads the same input again.
+    assert_eq!(Ok(Status::Ready(21)), cpu.step());
+
+    // Only the most recent instructions are kept.
+    cpu.set_history_limit(2);
+    assert_eq!(2, cpu.history().len());
+    assert_eq!(Some(8), cpu.history().back().map(|undo| undo.pc));
+    assert!(cpu.undo().is_some());
+    assert!(cpu.undo().is_some());
+    assert_eq!(None, cpu.undo());
+    assert_eq!(4, cpu.pc());
+}
+
+#[test]
+fn test_undo_growth() {
+    use crate::intcode::CPU;
+
+    // Taking back a write that grew memory also drops the page it allocated.
+    let mut cpu = CPU::new(&[1101, 1, 1, 1000, 99]);
+    let before = cpu.snapshot();
+    cpu.record_history(true);
+    assert_eq!(Ok(None), cpu.execute());
+    assert_eq!(2, cpu.memory().pages());
+    assert!(cpu.undo().is_some());
+    assert_eq!(1, cpu.memory().pages());
+    assert_eq!(before, cpu.snapshot());
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::intcode::memory::Memory;
+use crate::intcode::op::Op;
+
+/// What an opcode does, as far as the CPU is concerned.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Instruction {
+    /// One of the standard operations.
+    Standard(Op),
+    /// An operation run by `InstructionSet::execute`, with `arity` parameters of which
+    /// the last is written to if `writes` is set.
+    Custom { arity: usize, writes: bool },
+}
+
+impl Instruction {
+    pub fn arity(self) -> usize {
+        match self {
+            Instruction::Standard(op) => op.arity(),
+            Instruction::Custom { arity, .. } => arity,
+        }
+    }
+
+    pub fn writes(self) -> bool {
+        match self {
+            Instruction::Standard(op) => op.writes(),
+            Instruction::Custom { writes, .. } => writes,
+        }
+    }
+
+    /// Number of parameters that are read, i.e. all but the write target.
+    pub fn reads(self) -> usize {
+        self.arity() - self.writes() as usize
+    }
+}
+
+/// What a custom instruction asks the CPU to do.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Effect {
+    /// Move on to the next instruction.
+    Continue,
+    /// Store a value through the write parameter, then move on.
+    Write(i64),
+    Jump(i64),
+    Output(i64),
+    Halt,
+    /// Halt with an exit code, see `CPU::exit_code`.
+    Exit(i64),
+}
+
+/// The opcodes and parameter modes a machine understands, see `CPU::set_instruction_set`.
+pub trait InstructionSet {
+    /// Look up an opcode, `None` if it isn't part of the set.
+    fn instruction(&self, opcode: u8) -> Option<Instruction>;
+
+    /// Whether parameters may use `mode`.
+    fn has_mode(&self, mode: u8) -> bool {
+        mode <= 2
+    }
+
+    /// Read a parameter in a mode other than position, immediate or relative.
+    /// Custom modes can only be read, not written through.
+    fn read_mode(
+        &self,
+        _mode: u8,
+        _raw: i64,
+        _memory: &Memory,
+        _relative_base: i64,
+    ) -> Option<i64> {
+        None
+    }
+
+    /// Run a custom instruction, given the values of the parameters it reads.
+    fn execute(&self, _opcode: u8, _operands: &[i64]) -> Effect {
+        Effect::Continue
+    }
+}
+
+/// The full Intcode instruction set, what every machine runs unless told otherwise.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Standard;
+
+impl InstructionSet for Standard {
+    fn instruction(&self, opcode: u8) -> Option<Instruction> {
+        Op::from_opcode(opcode).map(Instruction::Standard)
+    }
+}
+
+/// A restricted profile of the standard instruction set.
+#[derive(Debug, Clone)]
+pub struct Subset {
+    ops: Vec<Op>,
+    relative: bool,
+}
+
+impl Subset {
+    pub fn new(ops: &[Op], relative: bool) -> Subset {
+        Subset {
+            ops: ops.to_vec(),
+            relative,
+        }
+    }
+
+    /// What the day 5 diagnostics need: no relative base, so no `ARB` or relative mode.
+    pub fn day5() -> Subset {
+        use Op::*;
+        Subset::new(&[Add, Mul, In, Out, Jnz, Jz, Lt, Eq, Hlt], false)
+    }
+}
+
+impl InstructionSet for Subset {
+    fn instruction(&self, opcode: u8) -> Option<Instruction> {
+        Op::from_opcode(opcode)
+            .filter(|op| self.ops.contains(op))
+            .map(Instruction::Standard)
+    }
+
+    fn has_mode(&self, mode: u8) -> bool {
+        mode < 2 || (mode == 2 && self.relative)
+    }
+}
+
+type Handler = Box<dyn Fn(&[i64]) -> Effect>;
+type ModeReader = Box<dyn Fn(i64, &Memory, i64) -> Option<i64>>;
+
+/// Another instruction set with extra opcodes and parameter modes registered on top.
+pub struct Extended {
+    base: Rc<dyn InstructionSet>,
+    ops: HashMap<u8, (Instruction, Handler)>,
+    modes: HashMap<u8, ModeReader>,
+}
+
+impl Extended {
+    pub fn new(base: Rc<dyn InstructionSet>) -> Extended {
+        Extended {
+            base,
+            ops: HashMap::new(),
+            modes: HashMap::new(),
+        }
+    }
+
+    /// Register `opcode`, replacing whatever the base set does with it.
+    ///
+    /// Panics if `arity` is over 3, like the standard instructions, or if an
+    /// instruction that writes has no parameter to write through.
+    pub fn opcode<F>(mut self, opcode: u8, arity: usize, writes: bool, handler: F) -> Extended
+    where
+        F: Fn(&[i64]) -> Effect + 'static,
+    {
+        assert!(
+            arity <= 3,
+            "opcode {} takes {} parameters, at most 3 are supported",
+            opcode,
+            arity
+        );
+        assert!(
+            !writes || arity > 0,
+            "opcode {} writes but has no parameter to write through",
+            opcode
+        );
+        let instruction = Instruction::Custom { arity, writes };
+        self.ops.insert(opcode, (instruction, Box::new(handler)));
+        self
+    }
+
+    /// Register a read-only parameter mode, `reader` gets the raw parameter, memory
+    /// and relative base.
+    pub fn mode<F>(mut self, mode: u8, reader: F) -> Extended
+    where
+        F: Fn(i64, &Memory, i64) -> Option<i64> + 'static,
+    {
+        self.modes.insert(mode, Box::new(reader));
+        self
+    }
+}
+
+impl InstructionSet for Extended {
+    fn instruction(&self, opcode: u8) -> Option<Instruction> {
+        match self.ops.get(&opcode) {
+            Some((instruction, _)) => Some(*instruction),
+            None => self.base.instruction(opcode),
+        }
+    }
+
+    fn has_mode(&self, mode: u8) -> bool {
+        self.modes.contains_key(&mode) || self.base.has_mode(mode)
+    }
+
+    fn read_mode(&self, mode: u8, raw: i64, memory: &Memory, relative_base: i64) -> Option<i64> {
+        match self.modes.get(&mode) {
+            Some(reader) => reader(raw, memory, relative_base),
+            None => self.base.read_mode(mode, raw, memory, relative_base),
+        }
+    }
+
+    fn execute(&self, opcode: u8, operands: &[i64]) -> Effect {
+        match self.ops.get(&opcode) {
+            Some((_, handler)) => handler(operands),
+            None => self.base.execute(opcode, operands),
+        }
+    }
+}
+
+#[test]
+fn test_subset() {
+    use crate::intcode::error::{IntcodeError, Location};
+    use crate::intcode::CPU;
+
+    let day5 = || {
+        let mut cpu = CPU::new(&[109, 1, 204, 0, 99]);
+        cpu.set_instruction_set(Rc::new(Subset::day5()));
+        cpu
+    };
+    let at = Location {
+        pc: 0,
+        instruction: 109,
+        relative_base: 0,
+    };
+    assert_eq!(
+        Err(IntcodeError::UnknownOpcode { opcode: 9, at }),
+        day5().step()
+    );
+
+    let mut cpu = day5();
+    cpu.set_memory(0, 1105);
+    cpu.set_memory(1, 1);
+    cpu.set_memory(2, 4);
+    cpu.set_memory(4, 204);
+    cpu.set_memory(5, 0);
+    assert_eq!(
+        Err(IntcodeError::BadParameterMode {
+            mode: 2,
+            at: Location {
+                pc: 4,
+                instruction: 204,
+                relative_base: 0
+            }
+        }),
+        cpu.step()
+    );
+
+    let mut cpu = CPU::new(&[109, 1, 204, 0, 99]);
+    cpu.set_instruction_set(Rc::new(Standard));
+    assert_eq!(vec![1], cpu.run_to_completion().unwrap());
+}
+
+#[test]
+fn test_extended() -> anyhow::Result<()> {
+    use std::cell::RefCell;
+
+    use crate::intcode::{Status, CPU};
+
+    let printed = Rc::new(RefCell::new(Vec::new()));
+    let log = printed.clone();
+    let isa = Extended::new(Rc::new(Standard))
+        // Debug print.
+        .opcode(50, 1, false, move |operands| {
+            log.borrow_mut().push(operands[0]);
+            Effect::Continue
+        })
+        // Host call, squares its argument.
+        .opcode(51, 2, true, |operands| {
+            Effect::Write(operands[0] * operands[0])
+        })
+        // Halt with an exit code.
+        .opcode(52, 1, false, |operands| Effect::Exit(operands[0]))
+        // Double indirect, read the address stored at an address.
+        .mode(3, |raw, memory, _| {
+            let address = memory.get(raw as usize)?;
+            memory.get(address as usize)
+        });
+
+    let program = vec![
+        104, 5, // OUT #5
+        150, 7, // debug print #7
+        151, 9, 20, // square #9 -> [20]
+        4, 20, // OUT [20]
+        352, 12, // exit with [[12]]
+        99, 13, 3,
+    ];
+
+    let mut cpu = CPU::new(&program);
+    cpu.set_instruction_set(Rc::new(isa));
+    let start = cpu.snapshot();
+    assert_eq!(Status::Ready(5), cpu.step()?);
+    assert_eq!(Status::Ready(81), cpu.step()?);
+    assert_eq!(Status::Halted, cpu.step()?);
+    assert_eq!(Some(3), cpu.exit_code());
+    assert_eq!(vec![7], *printed.borrow());
+
+    // Restoring goes back to before the exit.
+    cpu.restore(&start);
+    assert_eq!(None, cpu.exit_code());
+    assert_eq!(0, cpu.usage().instructions);
+    assert_eq!(Status::Ready(5), cpu.step()?);
+
+    // Writing needs a write parameter.
+    let isa = Extended::new(Rc::new(Standard)).opcode(50, 1, false, |_| Effect::Write(1));
+    let mut cpu = CPU::new(&[150, 0, 99]);
+    cpu.set_instruction_set(Rc::new(isa));
+    assert_eq!(
+        "write from an instruction without a write parameter at pc 0 (instruction 150, relative base 0)",
+        cpu.step().unwrap_err().to_string()
+    );
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "opcode 50 takes 4 parameters, at most 3 are supported")]
+fn test_arity_too_large() {
+    Extended::new(Rc::new(Standard)).opcode(50, 4, false, |_| Effect::Continue);
+}
+
+#[test]
+#[should_panic(expected = "opcode 50 writes but has no parameter to write through")]
+fn test_write_without_parameter() {
+    Extended::new(Rc::new(Standard)).opcode(50, 0, true, |_| Effect::Write(1));
+}
@@ -0,0 +1,141 @@
+use std::fmt;
+
+/// Budgets for running untrusted programs, see `CPU::set_limits`. Unlimited by default.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Limits {
+    pub instructions: Option<u64>,
+    /// Writes at or above this address fail, reads past it still see zero.
+    pub memory: Option<usize>,
+    pub outputs: Option<u64>,
+}
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits::default()
+    }
+
+    pub fn instructions(mut self, count: u64) -> Limits {
+        self.instructions = Some(count);
+        self
+    }
+
+    pub fn memory(mut self, words: usize) -> Limits {
+        self.memory = Some(words);
+        self
+    }
+
+    pub fn outputs(mut self, count: u64) -> Limits {
+        self.outputs = Some(count);
+        self
+    }
+
+    /// Whether there's nothing to check.
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    /// The limit running one more instruction would exceed, if any.
+    pub fn check_instruction(&self, usage: &Usage) -> Option<Limit> {
+        self.instructions
+            .filter(|max| usage.instructions >= *max)
+            .map(Limit::Instructions)
+    }
+
+    pub fn check_write(&self, address: usize) -> Option<Limit> {
+        self.memory.filter(|max| address >= *max).map(Limit::Memory)
+    }
+
+    pub fn check_output(&self, usage: &Usage) -> Option<Limit> {
+        self.outputs
+            .filter(|max| usage.outputs >= *max)
+            .map(Limit::Outputs)
+    }
+}
+
+/// What a machine has used up so far.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Usage {
+    pub instructions: u64,
+    pub outputs: u64,
+}
+
+/// A limit that was hit, along with its value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Limit {
+    Instructions(u64),
+    Memory(usize),
+    Outputs(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Instructions(max) => write!(f, "limit of {} instructions", max),
+            Limit::Memory(max) => write!(f, "limit of {} words of memory", max),
+            Limit::Outputs(max) => write!(f, "limit of {} outputs", max),
+        }
+    }
+}
+
+#[test]
+fn test_limits() {
+    use crate::intcode::error::{IntcodeError, Location};
+    use crate::intcode::fast::FastCPU;
+    use crate::intcode::{Status, CPU};
+
+    let looping = [1105, 1, 0];
+    let at = Location {
+        pc: 0,
+        instruction: 1105,
+        relative_base: 0,
+    };
+    let mut cpu = CPU::new(&looping);
+    cpu.set_limits(Limits::new().instructions(10));
+    assert_eq!(
+        Err(IntcodeError::LimitExceeded {
+            limit: Limit::Instructions(10),
+            at
+        }),
+        cpu.step()
+    );
+    assert_eq!(10, cpu.usage().instructions);
+
+    let mut fast = FastCPU::from_cpu(CPU::new(&looping));
+    fast.set_limits(Limits::new().instructions(1000));
+    assert!(fast.step().is_err());
+    assert_eq!(1000, fast.cpu().usage().instructions);
+
+    // Output forever, then write further and further out.
+    let flood = [104, 1, 1105, 1, 0];
+    let mut cpu = CPU::new(&flood);
+    cpu.set_limits(Limits::new().outputs(3));
+    assert_eq!(3, cpu.outputs().take_while(Result::is_ok).count());
+    assert_eq!(
+        "exceeded the limit of 3 outputs at pc 0 (instruction 104, relative base 0)",
+        cpu.step().unwrap_err().to_string()
+    );
+
+    let grow = [109, 1, 21101, 7, 0, 1000, 1105, 1, 0];
+    let mut cpu = CPU::new(&grow);
+    cpu.set_limits(Limits::new().memory(1200));
+    match cpu.step() {
+        Err(IntcodeError::LimitExceeded {
+            limit: Limit::Memory(1200),
+            at,
+        }) => assert_eq!(200, at.relative_base),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(1200, cpu.memory().len());
+
+    let mut fast = FastCPU::new(&grow);
+    fast.set_limits(Limits::new().memory(1200));
+    assert!(fast.step().is_err());
+    assert_eq!(cpu.usage(), fast.cpu().usage());
+    assert_eq!(cpu.memory().to_vec(), fast.cpu().memory().to_vec());
+
+    // Well-behaved programs are unaffected.
+    let mut cpu = CPU::new(&[104, 1, 99]);
+    cpu.set_limits(Limits::new().instructions(2).outputs(1).memory(3));
+    assert_eq!(Ok(Status::Ready(1)), cpu.step());
+    assert_eq!(Ok(Status::Halted), cpu.step());
+}
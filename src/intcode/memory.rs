@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Number of words in a page.
+pub const PAGE_SIZE: usize = 512;
+
+/// Pages below this index are kept in a vector, anything above goes in a map.
+const DENSE_PAGES: usize = 1 << 16;
+
+type Page = [i64; PAGE_SIZE];
+
+/// Intcode memory split into copy-on-write pages.
+///
+/// Cloning only bumps a reference count per page, a page is copied the first time
+/// either side writes to it. That makes forking a machine cheap no matter how big
+/// its program is.
+///
+/// Every non-negative address is valid. Pages that were never written share a single
+/// zero page, and pages far beyond the program are stored sparsely, so a write to
+/// a huge address costs one page rather than everything below it.
+#[derive(Clone, Debug)]
+pub struct Memory {
+    dense: Vec<Arc<Page>>,
+    sparse: BTreeMap<usize, Arc<Page>>,
+    zero: Arc<Page>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn new(contents: &[i64]) -> Memory {
+        let dense = contents
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = [0; PAGE_SIZE];
+                page[..chunk.len()].copy_from_slice(chunk);
+                Arc::new(page)
+            })
+            .collect();
+        Memory {
+            dense,
+            sparse: BTreeMap::new(),
+            zero: Arc::new([0; PAGE_SIZE]),
+            len: contents.len(),
+        }
+    }
+
+    /// One past the highest address that was loaded or written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn page(&self, index: usize) -> Option<&Page> {
+        if index < DENSE_PAGES {
+            self.dense.get(index).map(|p| &**p)
+        } else {
+            self.sparse.get(&index).map(|p| &**p)
+        }
+    }
+
+    /// Read an address that was loaded or written, `None` past the end of memory.
+    pub fn get(&self, address: usize) -> Option<i64> {
+        if address >= self.len {
+            return None;
+        }
+        Some(
+            self.page(address / PAGE_SIZE)
+                .map_or(0, |p| p[address % PAGE_SIZE]),
+        )
+    }
+
+    pub fn set(&mut self, address: usize, value: i64) {
+        let index = address / PAGE_SIZE;
+        let page = if index < DENSE_PAGES {
+            if self.dense.len() <= index {
+                let zero = &self.zero;
+                self.dense.resize_with(index + 1, || zero.clone());
+            }
+            &mut self.dense[index]
+        } else {
+            let zero = &self.zero;
+            self.sparse.entry(index).or_insert_with(|| zero.clone())
+        };
+        Arc::make_mut(page)[address % PAGE_SIZE] = value;
+        self.len = self.len.max(address + 1);
+    }
+
+    /// Shrink memory back to `len` words, e.g. to take back a write that grew it.
+    /// Pages wholly past `len` are dropped, the rest of a page partly past it must
+    /// already be zero.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.len {
+            return;
+        }
+        self.len = len;
+        let pages = len.div_ceil(PAGE_SIZE);
+        self.dense.truncate(pages);
+        self.sparse.split_off(&pages);
+    }
+
+    /// Pages that hold data, skipping the ones still pointing at the zero page.
+    fn materialized(&self) -> impl Iterator<Item = (usize, &Arc<Page>)> {
+        self.dense
+            .iter()
+            .enumerate()
+            .chain(self.sparse.iter().map(|(i, p)| (*i, p)))
+            .filter(move |(_, p)| !Arc::ptr_eq(p, &self.zero))
+    }
+
+    /// Number of pages actually allocated.
+    pub fn pages(&self) -> usize {
+        self.materialized().count()
+    }
+
+    /// Approximate number of bytes used for memory contents.
+    pub fn bytes(&self) -> usize {
+        self.pages() * PAGE_SIZE * std::mem::size_of::<i64>()
+    }
+
+    /// Number of pages still shared with `other`, i.e. not copied by a write yet.
+    pub fn shared_pages(&self, other: &Memory) -> usize {
+        let dense = self
+            .dense
+            .iter()
+            .zip(&other.dense)
+            .filter(|(p, q)| Arc::ptr_eq(p, q) && !Arc::ptr_eq(p, &self.zero))
+            .count();
+        let sparse = self
+            .sparse
+            .iter()
+            .filter(|(i, p)| other.sparse.get(i).is_some_and(|q| Arc::ptr_eq(p, q)))
+            .count();
+        dense + sparse
+    }
+
+    /// The contents of every allocated page, keyed by the address of its first word.
+    pub fn chunks(&self) -> BTreeMap<usize, Vec<i64>> {
+        self.materialized()
+            .map(|(i, p)| {
+                let start = i * PAGE_SIZE;
+                let end = self.len.min(start + PAGE_SIZE);
+                (start, p[..end - start].to_vec())
+            })
+            .collect()
+    }
+
+    /// Rebuild memory from `chunks`, the inverse of `Memory::chunks`.
+    ///
+    /// Panics if a chunk runs past `len`.
+    pub fn from_chunks(len: usize, chunks: &BTreeMap<usize, Vec<i64>>) -> Memory {
+        let mut memory = Memory::new(&[]);
+        for (start, values) in chunks {
+            for (i, value) in values.iter().enumerate() {
+                memory.set(start + i, *value);
+            }
+        }
+        assert!(
+            memory.len <= len,
+            "memory chunks run past the end of memory at {}",
+            len
+        );
+        memory.len = len;
+        memory
+    }
+
+    /// Copy out `count` words starting at `start`.
+    pub fn slice(&self, start: usize, count: usize) -> Vec<i64> {
+        (start..start.saturating_add(count))
+            .map(|address| self.get(address).unwrap_or(0))
+            .collect()
+    }
+
+    pub fn to_vec(&self) -> Vec<i64> {
+        self.slice(0, self.len)
+    }
+}
+
+#[test]
+fn test_copy_on_write() {
+    let contents: Vec<i64> = (0..2000).collect();
+    let mut memory = Memory::new(&contents);
+    assert_eq!(2000, memory.len());
+    assert_eq!(Some(1999), memory.get(1999));
+    assert_eq!(None, memory.get(2000));
+
+    let mut fork = memory.clone();
+    assert_eq!(4, fork.shared_pages(&memory));
+
+    fork.set(5, -5);
+    assert_eq!(3, fork.shared_pages(&memory));
+    assert_eq!(Some(5), memory.get(5));
+    assert_eq!(Some(-5), fork.get(5));
+
+    memory.set(5000, 1);
+    assert_eq!(5001, memory.len());
+    assert_eq!(Some(0), memory.get(4000));
+    assert_eq!(2000, fork.len());
+
+    let mut expected = contents;
+    expected[5] = -5;
+    assert_eq!(expected, fork.to_vec());
+}
+
+#[test]
+fn test_sparse() {
+    let mut memory = Memory::new(&[1, 2, 3]);
+    assert_eq!(1, memory.pages());
+
+    // Far away writes only allocate the page they land on.
+    let huge = i64::MAX as usize - 7;
+    memory.set(huge, 42);
+    memory.set(1 << 40, 7);
+    assert_eq!(3, memory.pages());
+    assert_eq!(3 * PAGE_SIZE * 8, memory.bytes());
+    assert_eq!(Some(42), memory.get(huge));
+    assert_eq!(Some(7), memory.get(1 << 40));
+    assert_eq!(Some(0), memory.get(huge - 1));
+    assert_eq!(Some(0), memory.get(1 << 30));
+
+    // Growing the dense part shares the zero page.
+    memory.set(1_000_000, 1);
+    assert_eq!(4, memory.pages());
+
+    let restored = Memory::from_chunks(memory.len(), &memory.chunks());
+    assert_eq!(memory.len(), restored.len());
+    assert_eq!(Some(42), restored.get(huge));
+    assert_eq!(Some(1), restored.get(1_000_000));
+    assert_eq!(vec![1, 2, 3, 0], restored.slice(0, 4));
+
+    memory.truncate(3);
+    assert_eq!(1, memory.pages());
+    assert_eq!(vec![1, 2, 3], memory.to_vec());
+    assert_eq!(1, memory.chunks().len());
+}
@@ -0,0 +1,317 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Context, Result};
+
+use crate::intcode::limits::Limits;
+use crate::intcode::{Status, CPU};
+
+/// How many instructions a machine runs before the others get a turn.
+const TURN: usize = 10_000;
+
+/// Why a network stopped running.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every machine has halted.
+    Halted,
+    /// No machine can make progress: the ones still alive are all waiting on empty queues.
+    Idle,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum State {
+    Runnable,
+    Blocked,
+    Halted,
+}
+
+struct Machine {
+    name: String,
+    cpu: CPU,
+    outputs: Vec<String>,
+    state: State,
+}
+
+/// A set of Intcode machines wired together by named queues.
+///
+/// Every machine reads from the queue carrying its own name and sends each output to
+/// all the queues it is connected to. Queues that don't belong to a machine act as
+/// sinks the caller can inspect once the network stops.
+///
+/// Machines take turns of a bounded number of instructions, so one that never waits
+/// on input doesn't starve the others, though the network then never stops either.
+#[derive(Default)]
+pub struct Network {
+    machines: Vec<Machine>,
+    queues: HashMap<String, VecDeque<i64>>,
+    limits: Limits,
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network::default()
+    }
+
+    /// Add a machine reading from the queue `name`, which must not belong to another.
+    pub fn add_machine(&mut self, name: &str, program: &[i64]) -> Result<()> {
+        if self.machines.iter().any(|m| m.name == name) {
+            return Err(anyhow!("there already is a machine called {}", name));
+        }
+        let mut cpu = CPU::new(program);
+        cpu.set_limits(self.limits);
+        self.machines.push(Machine {
+            name: name.to_string(),
+            cpu,
+            outputs: Vec::new(),
+            state: State::Runnable,
+        });
+        self.queues.entry(name.to_string()).or_default();
+        Ok(())
+    }
+
+    /// Give every machine, including ones added later, its own `limits`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        for machine in &mut self.machines {
+            machine.cpu.set_limits(limits);
+        }
+    }
+
+    /// Send every output of machine `from` to queue `to`.
+    pub fn connect(&mut self, from: &str, to: &str) -> Result<()> {
+        let machine = self
+            .machines
+            .iter_mut()
+            .find(|m| m.name == from)
+            .context(format!("unknown machine {}", from))?;
+        machine.outputs.push(to.to_string());
+        self.queues.entry(to.to_string()).or_default();
+        Ok(())
+    }
+
+    /// Connect machines from a wiring spec such as `A -> B -> C, C -> out`.
+    ///
+    /// Links are separated by commas or newlines and each `->` sends the outputs of the
+    /// left hand side to the queue on the right hand side.
+    pub fn wire(&mut self, spec: &str) -> Result<()> {
+        for link in spec.split([',', '\n']) {
+            let names: Vec<&str> = link.split("->").map(str::trim).collect();
+            if names.len() == 1 && names[0].is_empty() {
+                continue;
+            }
+            if names.len() < 2 || names.iter().any(|n| n.is_empty()) {
+                return Err(anyhow!("invalid link {:?}", link.trim()));
+            }
+            for pair in names.windows(2) {
+                self.connect(pair[0], pair[1])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Push a value onto the named queue.
+    pub fn push(&mut self, queue: &str, value: i64) {
+        self.queues
+            .entry(queue.to_string())
+            .or_default()
+            .push_back(value);
+    }
+
+    pub fn queue(&self, name: &str) -> Option<&VecDeque<i64>> {
+        self.queues.get(name)
+    }
+
+    pub fn queue_mut(&mut self, name: &str) -> Option<&mut VecDeque<i64>> {
+        self.queues.get_mut(name)
+    }
+
+    pub fn machine(&self, name: &str) -> Option<&CPU> {
+        self.machines
+            .iter()
+            .find(|m| m.name == name)
+            .map(|m| &m.cpu)
+    }
+
+    /// Names of the machines that are waiting on an empty queue.
+    pub fn blocked(&self) -> Vec<&str> {
+        self.machines
+            .iter()
+            .filter(|m| m.state == State::Blocked)
+            .map(|m| m.name.as_str())
+            .collect()
+    }
+
+    /// Run every runnable machine until the whole network halts or goes idle.
+    ///
+    /// Blocked machines are woken up as soon as something lands in their queue, so
+    /// `run` can be called again after pushing more input into an idle network.
+    pub fn run(&mut self) -> Result<Outcome> {
+        loop {
+            let mut progress = false;
+            for index in 0..self.machines.len() {
+                progress |= self.run_machine(index)?;
+            }
+
+            if !progress {
+                break;
+            }
+        }
+
+        if self.machines.iter().all(|m| m.state == State::Halted) {
+            Ok(Outcome::Halted)
+        } else {
+            Ok(Outcome::Idle)
+        }
+    }
+
+    /// Run a single machine until it blocks, halts or its turn is up, returning whether
+    /// it did anything.
+    fn run_machine(&mut self, index: usize) -> Result<bool> {
+        let machine = &mut self.machines[index];
+        if machine.state == State::Halted {
+            return Ok(false);
+        }
+
+        let queue = self.queues.entry(machine.name.clone()).or_default();
+        if machine.state == State::Blocked && queue.is_empty() {
+            return Ok(false);
+        }
+        for value in queue.drain(..) {
+            machine.cpu.add_input(value);
+        }
+        machine.state = State::Runnable;
+
+        for _ in 0..TURN {
+            let status = machine
+                .cpu
+                .execute()
+                .with_context(|| format!("machine {}", machine.name))?;
+            match status {
+                None => {}
+                Some(Status::Ready(value)) => {
+                    for name in &machine.outputs {
+                        self.queues
+                            .entry(name.clone())
+                            .or_default()
+                            .push_back(value);
+                    }
+                    // Our own queue may have just been fed, pick that up before blocking.
+                    let queue = self.queues.entry(machine.name.clone()).or_default();
+                    for value in queue.drain(..) {
+                        machine.cpu.add_input(value);
+                    }
+                }
+                Some(Status::NeedsInput) => {
+                    machine.state = State::Blocked;
+                    break;
+                }
+                Some(Status::Halted) => {
+                    machine.state = State::Halted;
+                    break;
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_feedback_ring() -> Result<()> {
+    let program = vec![
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+
+    let mut network = Network::new();
+    for (name, phase) in ["A", "B", "C", "D", "E"].iter().zip(&[9, 8, 7, 6, 5]) {
+        network.add_machine(name, &program)?;
+        network.push(name, *phase);
+    }
+    network.wire("A -> B -> C -> D -> E -> A, E -> thrusters")?;
+    network.push("A", 0);
+
+    assert_eq!(Outcome::Halted, network.run()?);
+    assert_eq!(
+        Some(&139629729),
+        network.queue("thrusters").and_then(|q| q.back())
+    );
+    Ok(())
+}
+
+#[test]
+fn test_fan_out() -> Result<()> {
+    // Doubles its input, and adds one to its input.
+    let double = vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0];
+    let increment = vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0];
+
+    let mut network = Network::new();
+    network.add_machine("double", &double)?;
+    network.add_machine("left", &increment)?;
+    network.add_machine("right", &double)?;
+    network.wire("double -> left -> out\ndouble -> right -> out")?;
+    network.push("double", 5);
+
+    assert_eq!(Outcome::Halted, network.run()?);
+    let mut out: Vec<i64> = network.queue("out").unwrap().iter().copied().collect();
+    out.sort();
+    assert_eq!(vec![11, 20], out);
+    Ok(())
+}
+
+#[test]
+fn test_idle() -> Result<()> {
+    // Both machines wait on each other forever.
+    let echo = vec![3, 7, 4, 7, 1105, 1, 0, 0];
+
+    let mut network = Network::new();
+    network.add_machine("ping", &echo)?;
+    network.add_machine("pong", &echo)?;
+    network.wire("ping -> pong -> ping")?;
+    assert_eq!(Outcome::Idle, network.run()?);
+    assert_eq!(vec!["ping", "pong"], network.blocked());
+
+    // An idle machine wakes up once its queue is fed.
+    let mut network = Network::new();
+    network.add_machine("echo", &echo)?;
+    network.wire("echo -> out")?;
+    assert_eq!(Outcome::Idle, network.run()?);
+    network.push("echo", 3);
+    network.push("echo", 4);
+    assert_eq!(Outcome::Idle, network.run()?);
+    assert_eq!(Some(&VecDeque::from(vec![3, 4])), network.queue("out"));
+
+    assert!(network.wire("echo ->").is_err());
+    assert!(network.wire("nobody -> echo").is_err());
+    assert!(network.add_machine("echo", &echo).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_turns() -> Result<()> {
+    // A machine spinning forever without input doesn't keep the others from running.
+    let spin = vec![1105, 1, 0];
+    let double = vec![3, 9, 1002, 9, 2, 9, 4, 9, 99, 0];
+
+    let mut network = Network::new();
+    network.add_machine("spin", &spin)?;
+    network.add_machine("double", &double)?;
+    network.wire("double -> out")?;
+    network.push("double", 21);
+    for _ in 0..2 {
+        network.run_machine(0)?;
+        network.run_machine(1)?;
+    }
+    assert_eq!(Some(&VecDeque::from(vec![42])), network.queue("out"));
+
+    // Errors say which machine failed.
+    let mut network = Network::new();
+    network.add_machine("broken", &[42])?;
+    assert_eq!("machine broken", network.run().unwrap_err().to_string());
+
+    // With limits, the spinning machine eventually fails instead of running forever.
+    let mut network = Network::new();
+    network.add_machine("spin", &spin)?;
+    network.set_limits(Limits::new().instructions(100_000));
+    assert_eq!("machine spin", network.run().unwrap_err().to_string());
+    Ok(())
+}
@@ -0,0 +1,111 @@
+/// The Intcode instruction set, one variant per opcode.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    Add,
+    Mul,
+    In,
+    Out,
+    Jnz,
+    Jz,
+    Lt,
+    Eq,
+    Arb,
+    Hlt,
+}
+
+const OPS: [Op; 10] = [
+    Op::Add,
+    Op::Mul,
+    Op::In,
+    Op::Out,
+    Op::Jnz,
+    Op::Jz,
+    Op::Lt,
+    Op::Eq,
+    Op::Arb,
+    Op::Hlt,
+];
+
+impl Op {
+    pub fn from_opcode(opcode: u8) -> Option<Op> {
+        match opcode {
+            1 => Some(Op::Add),
+            2 => Some(Op::Mul),
+            3 => Some(Op::In),
+            4 => Some(Op::Out),
+            5 => Some(Op::Jnz),
+            6 => Some(Op::Jz),
+            7 => Some(Op::Lt),
+            8 => Some(Op::Eq),
+            9 => Some(Op::Arb),
+            99 => Some(Op::Hlt),
+            _ => None,
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<Op> {
+        OPS.iter()
+            .copied()
+            .find(|op| op.mnemonic().eq_ignore_ascii_case(mnemonic))
+    }
+
+    pub fn opcode(self) -> u8 {
+        match self {
+            Op::Add => 1,
+            Op::Mul => 2,
+            Op::In => 3,
+            Op::Out => 4,
+            Op::Jnz => 5,
+            Op::Jz => 6,
+            Op::Lt => 7,
+            Op::Eq => 8,
+            Op::Arb => 9,
+            Op::Hlt => 99,
+        }
+    }
+
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Op::Add => "ADD",
+            Op::Mul => "MUL",
+            Op::In => "IN",
+            Op::Out => "OUT",
+            Op::Jnz => "JNZ",
+            Op::Jz => "JZ",
+            Op::Lt => "LT",
+            Op::Eq => "EQ",
+            Op::Arb => "ARB",
+            Op::Hlt => "HLT",
+        }
+    }
+
+    /// Number of parameters following the opcode.
+    pub fn arity(self) -> usize {
+        match self {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => 3,
+            Op::Jnz | Op::Jz => 2,
+            Op::In | Op::Out | Op::Arb => 1,
+            Op::Hlt => 0,
+        }
+    }
+
+    /// Whether the last parameter is an address that gets written to.
+    pub fn writes(self) -> bool {
+        matches!(self, Op::Add | Op::Mul | Op::Lt | Op::Eq | Op::In)
+    }
+
+    /// Number of parameters that are read, i.e. all but the write target.
+    pub fn reads(self) -> usize {
+        self.arity() - self.writes() as usize
+    }
+}
+
+#[test]
+fn test_table() {
+    for op in OPS.iter() {
+        assert_eq!(Some(*op), Op::from_opcode(op.opcode()));
+        assert_eq!(Some(*op), Op::from_mnemonic(op.mnemonic()));
+        assert!(op.reads() <= op.arity());
+    }
+    assert_eq!(None, Op::from_opcode(0));
+}
@@ -0,0 +1,102 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use crate::intcode::CPU;
+
+/// A named set of memory writes applied to a program before it runs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Patch {
+    pub name: String,
+    pub writes: Vec<(usize, i64)>,
+}
+
+impl Patch {
+    pub fn new(name: &str) -> Patch {
+        Patch {
+            name: name.to_string(),
+            writes: Vec::new(),
+        }
+    }
+
+    /// Add a write of `value` to `address`.
+    pub fn set(mut self, address: usize, value: i64) -> Patch {
+        self.writes.push((address, value));
+        self
+    }
+
+    /// Parse patches written one per line as `name: address = value, ...`.
+    ///
+    /// Blank lines and anything after a `;` are ignored.
+    pub fn parse(source: &str) -> Result<Vec<Patch>> {
+        let mut patches = Vec::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let context = || format!("line {}: {:?}", number + 1, line);
+
+            let (name, writes) = line.split_at(line.find(':').with_context(context)?);
+            let mut patch = Patch::new(name.trim());
+            for write in writes[1..].split(',') {
+                let (address, value) = write.split_at(write.find('=').with_context(context)?);
+                patch = patch.set(
+                    address.trim().parse().with_context(context)?,
+                    value[1..].trim().parse().with_context(context)?,
+                );
+            }
+            patches.push(patch);
+        }
+        Ok(patches)
+    }
+
+    /// Load a patch file, see `Patch::parse`.
+    pub fn load(path: &str) -> Result<Vec<Patch>> {
+        let source = fs::read_to_string(path).context(format!("failed to read {}", path))?;
+        Patch::parse(&source)
+    }
+
+    /// Load the patch called `name` from a patch file.
+    pub fn load_named(path: &str, name: &str) -> Result<Patch> {
+        Patch::load(path)?
+            .into_iter()
+            .find(|patch| patch.name == name)
+            .context(format!("no patch {} in {}", name, path))
+    }
+}
+
+impl CPU {
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        for (address, value) in &patch.writes {
+            self.set_memory(*address, *value);
+        }
+    }
+}
+
+#[test]
+fn test_patch() -> Result<()> {
+    let patches = Patch::parse(
+        "
+        ; Restore the alarm state.
+        1202: 1 = 12, 2 = 2
+        zero:0=0 ; trailing comment
+        ",
+    )?;
+    assert_eq!(
+        vec![
+            Patch::new("1202").set(1, 12).set(2, 2),
+            Patch::new("zero").set(0, 0)
+        ],
+        patches
+    );
+
+    let mut cpu = CPU::new(&[1, 0, 0, 0, 99]);
+    cpu.apply_patch(&patches[0]);
+    assert_eq!(vec![1, 12, 2, 0, 99], cpu.memory().to_vec());
+
+    assert!(Patch::parse("broken 1 = 2").is_err());
+    assert!(Patch::parse("broken: 1 2").is_err());
+    assert!(Patch::parse("broken: -1 = 2").is_err());
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rayon::prelude::*;
+
+use crate::intcode::limits::Limits;
+
+/// Instructions a candidate may run before it counts as stuck.
+pub const MAX_STEPS: u64 = 10_000_000;
+
+/// The limits to give each candidate's machine, so one that never halts fails instead
+/// of hanging the whole search.
+pub fn budget() -> Limits {
+    Limits::new().instructions(MAX_STEPS)
+}
+
+/// What a search is looking for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Objective {
+    /// Any parameters producing exactly this value, the first in the space wins.
+    Target(i64),
+    /// The parameters producing the biggest value, ties go to the first in the space.
+    Maximize,
+}
+
+/// How a search went.
+#[derive(Debug, Clone, Copy)]
+pub struct Stats {
+    /// Candidates run, a target search stops early once it has an answer.
+    pub evaluated: usize,
+    /// Candidates whose run returned an error, these are skipped.
+    pub failed: usize,
+    pub elapsed: Duration,
+    pub threads: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "evaluated {} candidates ({} failed) in {:.1?} on {} threads",
+            self.evaluated, self.failed, self.elapsed, self.threads
+        )
+    }
+}
+
+/// The best parameters found, if any, along with the value they produced.
+#[derive(Debug)]
+pub struct Found<P> {
+    pub best: Option<(P, i64)>,
+    pub stats: Stats,
+}
+
+/// Run `program` once for every parameter set in `space`, spread across threads.
+///
+/// `run` sets up a machine for one parameter set and returns the value to judge it
+/// by. Machines aren't shared between threads, so it builds its own from `program`,
+/// and it should give them `budget()` or some other limits.
+pub fn search<P, F>(space: &[P], program: &[i64], objective: Objective, run: F) -> Found<P>
+where
+    P: Clone + Sync,
+    F: Fn(&[i64], &P) -> Result<i64> + Sync,
+{
+    let start = Instant::now();
+    let evaluated = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+
+    let evaluate = |params: &P| {
+        evaluated.fetch_add(1, Ordering::Relaxed);
+        let value = run(program, params);
+        if value.is_err() {
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+        value.ok()
+    };
+
+    let candidates = space.par_iter().enumerate();
+    let best = match objective {
+        Objective::Target(target) => candidates
+            .find_first(|(_, params)| evaluate(params) == Some(target))
+            .map(|(_, params)| (params.clone(), target)),
+        Objective::Maximize => candidates
+            .filter_map(|(i, params)| evaluate(params).map(|value| (value, i)))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(value, i)| (space[i].clone(), value)),
+    };
+
+    Found {
+        best,
+        stats: Stats {
+            evaluated: evaluated.into_inner(),
+            failed: failed.into_inner(),
+            elapsed: start.elapsed(),
+            threads: rayon::current_num_threads(),
+        },
+    }
+}
+
+#[test]
+fn test_search() {
+    use crate::intcode::{run_program_with_limits, CPU};
+
+    // Output the input times three, failing on negative inputs.
+    let program = vec![3, 9, 1002, 9, 3, 9, 4, 9, 99, 0];
+    let run = |program: &[i64], input: &i64| {
+        if *input < 0 {
+            return Err(anyhow!("negative"));
+        }
+        run_program_with_limits(program, &[*input], budget())
+    };
+    let space: Vec<i64> = (-5..100).collect();
+
+    let found = search(&space, &program, Objective::Target(42), run);
+    assert_eq!(Some((14, 42)), found.best);
+    assert!(found.stats.evaluated >= 20);
+
+    let found = search(&space, &program, Objective::Maximize, run);
+    assert_eq!(Some((99, 297)), found.best);
+    assert_eq!(105, found.stats.evaluated);
+    assert_eq!(5, found.stats.failed);
+
+    let found = search(&space, &program, Objective::Target(1), run);
+    assert_eq!(None, found.best);
+
+    // Ties go to the earliest parameters.
+    let found = search(&[3, 1, 2], &[], Objective::Maximize, |_, _| Ok(7));
+    assert_eq!(Some((3, 7)), found.best);
+
+    // Parameters can be anything, here a patch of the first instruction.
+    let found = search(&[1, 2], &[1, 0, 0, 0, 99], Objective::Maximize, |p, op| {
+        let mut cpu = CPU::new(p);
+        cpu.set_limits(budget());
+        cpu.set_memory(0, *op);
+        cpu.run_to_completion()?;
+        Ok(cpu.get_memory(0))
+    });
+    assert_eq!(Some((2, 4)), found.best);
+
+    // A candidate that never halts runs out of budget and counts as failed.
+    let program = vec![3, 7, 1005, 7, 2, 104, 0, 99];
+    let run = |program: &[i64], input: &i64| {
+        let limits = Limits::new().instructions(1_000);
+        run_program_with_limits(program, &[*input], limits)
+    };
+    let found = search(&[0, 1], &program, Objective::Maximize, run);
+    assert_eq!(Some((0, 0)), found.best);
+    assert_eq!(1, found.stats.failed);
+}
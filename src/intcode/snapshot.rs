@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The memory, registers and queued input of a CPU, see `CPU::snapshot` and
+/// `CPU::restore`. Devices, limits, usage and the instruction set aren't included.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Allocated memory pages keyed by their first address.
+    pub mem: BTreeMap<usize, Vec<i64>>,
+    pub len: usize,
+    pub pc: usize,
+    pub relative_base: i64,
+    pub inputs: Vec<i64>,
+}
+
+impl Snapshot {
+    /// Write the snapshot to a JSON dump file.
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json).context(format!("failed to write {}", path))
+    }
+
+    /// Read a JSON dump file, checking that memory fits in its stated size.
+    pub fn load(path: &str) -> Result<Snapshot> {
+        let json = fs::read_to_string(path).context(format!("failed to read {}", path))?;
+        let snapshot: Snapshot = serde_json::from_str(&json)?;
+        for (start, values) in &snapshot.mem {
+            if start
+                .checked_add(values.len())
+                .is_none_or(|end| end > snapshot.len)
+            {
+                return Err(anyhow!(
+                    "{}: memory at {} runs past its size of {} words",
+                    path,
+                    start,
+                    snapshot.len
+                ));
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+#[test]
+fn test_snapshot() -> Result<()> {
+    use crate::intcode::{Status, CPU};
+
+    let program = vec![
+        109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+    ];
+    let mut cpu = CPU::new(&program);
+    for _ in 0..5 {
+        cpu.step()?;
+    }
+
+    // Fork off a copy through a dump and make sure both run the same from here.
+    let snapshot = cpu.snapshot();
+    let path = std::env::temp_dir().join("intcode-test-snapshot.json");
+    let path = path.to_str().unwrap();
+    snapshot.save(path)?;
+    let loaded = Snapshot::load(path)?;
+    fs::remove_file(path)?;
+    assert_eq!(snapshot, loaded);
+
+    let mut copy = CPU::from_snapshot(&loaded);
+    assert_eq!(Status::Ready(100), cpu.step()?);
+    assert_eq!(Status::Ready(100), copy.step()?);
+
+    // Rewind.
+    cpu.restore(&snapshot);
+    assert_eq!(Status::Ready(100), cpu.step()?);
+    assert_eq!(cpu.snapshot(), copy.snapshot());
+
+    // Memory past the stated size is rejected on load.
+    let path = std::env::temp_dir().join("intcode-test-malformed.json");
+    let path = path.to_str().unwrap();
+    fs::write(
+        path,
+        r#"{"mem":{"1024":[5]},"len":2,"pc":0,"relative_base":0,"inputs":[]}"#,
+    )?;
+    let err = Snapshot::load(path).unwrap_err();
+    fs::remove_file(path)?;
+    assert!(err
+        .to_string()
+        .ends_with("memory at 1024 runs past its size of 2 words"));
+    Ok(())
+}
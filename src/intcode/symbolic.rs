@@ -0,0 +1,528 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use anyhow::Result;
+
+use crate::intcode::limits::Limits;
+use crate::intcode::op::Op;
+use crate::intcode::patch::Patch;
+use crate::intcode::search::{search, Objective};
+use crate::intcode::{OpCodeMode, CPU};
+
+/// Give up on symbolic execution, or a brute force candidate, after this many
+/// instructions.
+const MAX_STEPS: usize = 1_000_000;
+
+/// A memory cell the solver is free to choose, within `range`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Unknown {
+    pub address: usize,
+    pub range: Range<i64>,
+}
+
+/// The value a solution is judged by.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Observe {
+    /// A memory cell once the program halts.
+    Memory(usize),
+    /// The last value the program outputs.
+    LastOutput,
+}
+
+/// `constant + sum(coefficient * x[i])` over the unknowns `x`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct Linear {
+    pub constant: i64,
+    /// Coefficients keyed by the index of the unknown, never zero.
+    pub terms: BTreeMap<usize, i64>,
+}
+
+impl Linear {
+    pub fn constant(value: i64) -> Linear {
+        Linear {
+            constant: value,
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn unknown(index: usize) -> Linear {
+        let mut terms = BTreeMap::new();
+        terms.insert(index, 1);
+        Linear { constant: 0, terms }
+    }
+
+    /// The value, if it doesn't depend on any unknowns.
+    pub fn as_constant(&self) -> Option<i64> {
+        if self.terms.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    pub fn coefficient(&self, index: usize) -> i64 {
+        self.terms.get(&index).copied().unwrap_or(0)
+    }
+
+    /// The sum, `None` if a coefficient or the constant overflows.
+    fn add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (index, coefficient) in &other.terms {
+            let term = sum.terms.entry(*index).or_insert(0);
+            *term = term.checked_add(*coefficient)?;
+        }
+        sum.terms.retain(|_, coefficient| *coefficient != 0);
+        Some(sum)
+    }
+
+    fn scale(&self, factor: i64) -> Option<Linear> {
+        if factor == 0 {
+            return Some(Linear::constant(0));
+        }
+        Some(Linear {
+            constant: self.constant.checked_mul(factor)?,
+            terms: self
+                .terms
+                .iter()
+                .map(|(i, c)| Some((*i, c.checked_mul(factor)?)))
+                .collect::<Option<_>>()?,
+        })
+    }
+
+    /// The value for the given unknowns, `None` if it overflows.
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        self.terms.iter().try_fold(self.constant, |sum, (i, c)| {
+            sum.checked_add(c.checked_mul(values[*i])?)
+        })
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, (index, coefficient)) in self.terms.iter().enumerate() {
+            let sign = if *coefficient < 0 { "-" } else { "+" };
+            match (n, coefficient.abs()) {
+                (0, 1) if *coefficient > 0 => write!(f, "x{}", index)?,
+                (0, _) if *coefficient > 0 => write!(f, "{}*x{}", coefficient, index)?,
+                (_, 1) => write!(f, " {} x{}", sign, index)?,
+                (_, c) => write!(f, " {} {}*x{}", sign, c, index)?,
+            }
+        }
+        match self.constant {
+            c if self.terms.is_empty() => write!(f, "{}", c),
+            0 => Ok(()),
+            c if c < 0 => write!(f, " - {}", -c),
+            c => write!(f, " + {}", c),
+        }
+    }
+}
+
+/// A cell's contents during symbolic execution.
+#[derive(Debug, Clone)]
+enum Value {
+    Linear(Linear),
+    /// Depends on the unknowns in a way we don't track, e.g. a product of two of them.
+    Opaque,
+}
+
+impl Value {
+    fn concrete(&self, what: &str, pc: usize) -> Result<i64> {
+        match self {
+            Value::Linear(linear) => linear
+                .as_constant()
+                .ok_or_else(|| anyhow!("{} at pc {} depends on the unknowns", what, pc)),
+            Value::Opaque => Err(anyhow!("{} at pc {} isn't linear", what, pc)),
+        }
+    }
+}
+
+struct Machine<'a> {
+    /// Sparse, like `Memory`, so far away writes don't allocate everything below them.
+    mem: BTreeMap<usize, Value>,
+    pc: usize,
+    relative_base: i64,
+    inputs: &'a [i64],
+    outputs: Vec<Value>,
+}
+
+impl<'a> Machine<'a> {
+    fn get(&self, address: usize) -> Value {
+        self.mem
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| Value::Linear(Linear::constant(0)))
+    }
+
+    fn address(&self, value: Option<i64>) -> Result<usize> {
+        let value = value.ok_or_else(|| anyhow!("overflow at pc {}", self.pc))?;
+        if value < 0 {
+            return Err(anyhow!("negative address {} at pc {}", value, self.pc));
+        }
+        Ok(value as usize)
+    }
+
+    fn read(&self, offset: usize, mode: u8) -> Result<Value> {
+        let raw = self.get(self.pc + offset);
+        let base = match mode {
+            0 => 0,
+            1 => return Ok(raw),
+            2 => self.relative_base,
+            _ => return Err(anyhow!("unknown mode {} at pc {}", mode, self.pc)),
+        };
+        // Reading through an address we can't pin down only matters if the value is used.
+        match raw.concrete("address", self.pc) {
+            Ok(address) => Ok(self.get(self.address(base.checked_add(address))?)),
+            Err(_) => Ok(Value::Opaque),
+        }
+    }
+
+    fn target(&self, offset: usize, mode: u8) -> Result<usize> {
+        let raw = self
+            .get(self.pc + offset)
+            .concrete("write address", self.pc)?;
+        match mode {
+            0 | 1 => self.address(Some(raw)),
+            2 => self.address(self.relative_base.checked_add(raw)),
+            _ => Err(anyhow!("unknown mode {} at pc {}", mode, self.pc)),
+        }
+    }
+
+    fn set(&mut self, address: usize, value: Value) {
+        self.mem.insert(address, value);
+    }
+
+    /// Execute one instruction, returning false once the program halts.
+    fn execute(&mut self) -> Result<bool> {
+        let code = self.get(self.pc).concrete("instruction", self.pc)?;
+        let modes: OpCodeMode = code.into();
+        let op = Op::from_opcode(modes.opcode)
+            .ok_or_else(|| anyhow!("unknown opcode {} at pc {}", code, self.pc))?;
+        let modes = [modes.p1, modes.p2, modes.p3];
+
+        let mut reads = Vec::with_capacity(2);
+        for (i, mode) in modes.iter().enumerate().take(op.reads()) {
+            reads.push(self.read(i + 1, *mode)?);
+        }
+        let mut next = self.pc + 1 + op.arity();
+
+        match op {
+            Op::Add | Op::Mul | Op::Lt | Op::Eq => {
+                let overflow = || anyhow!("overflow at pc {}", self.pc);
+                let value = match (op, &reads[0], &reads[1]) {
+                    (Op::Add, Value::Linear(a), Value::Linear(b)) => {
+                        Value::Linear(a.add(b).ok_or_else(overflow)?)
+                    }
+                    (Op::Mul, Value::Linear(a), Value::Linear(b)) => {
+                        match (a.as_constant(), b.as_constant()) {
+                            (Some(a), _) => Value::Linear(b.scale(a).ok_or_else(overflow)?),
+                            (_, Some(b)) => Value::Linear(a.scale(b).ok_or_else(overflow)?),
+                            _ => Value::Opaque,
+                        }
+                    }
+                    (Op::Lt, Value::Linear(a), Value::Linear(b)) => {
+                        match (a.as_constant(), b.as_constant()) {
+                            (Some(a), Some(b)) => Value::Linear(Linear::constant((a < b) as i64)),
+                            _ => Value::Opaque,
+                        }
+                    }
+                    (Op::Eq, Value::Linear(a), Value::Linear(b)) => {
+                        match (a.as_constant(), b.as_constant()) {
+                            (Some(a), Some(b)) => Value::Linear(Linear::constant((a == b) as i64)),
+                            _ => Value::Opaque,
+                        }
+                    }
+                    _ => Value::Opaque,
+                };
+                let target = self.target(3, modes[2])?;
+                self.set(target, value);
+            }
+            Op::In => {
+                let (input, rest) = self
+                    .inputs
+                    .split_first()
+                    .ok_or_else(|| anyhow!("ran out of inputs at pc {}", self.pc))?;
+                self.inputs = rest;
+                let target = self.target(1, modes[0])?;
+                self.set(target, Value::Linear(Linear::constant(*input)));
+            }
+            Op::Out => self.outputs.push(reads[0].clone()),
+            Op::Jnz | Op::Jz => {
+                let test = reads[0].concrete("jump condition", self.pc)?;
+                if (test != 0) == (op == Op::Jnz) {
+                    next = self.address(Some(reads[1].concrete("jump target", self.pc)?))?;
+                }
+            }
+            Op::Arb => {
+                let offset = reads[0].concrete("relative base", self.pc)?;
+                self.relative_base = self
+                    .relative_base
+                    .checked_add(offset)
+                    .ok_or_else(|| anyhow!("overflow at pc {}", self.pc))?;
+            }
+            Op::Hlt => return Ok(false),
+        }
+        self.pc = next;
+        Ok(true)
+    }
+}
+
+/// Run `program` with the cells at `unknowns` left symbolic, returning what the
+/// observed value is in terms of them.
+///
+/// Fails if control flow, addresses or the observed value itself depend on the
+/// unknowns in a way that can't be expressed linearly.
+pub fn symbolic(
+    program: &[i64],
+    inputs: &[i64],
+    unknowns: &[usize],
+    observe: Observe,
+) -> Result<Linear> {
+    let mut machine = Machine {
+        mem: program
+            .iter()
+            .enumerate()
+            .map(|(address, v)| (address, Value::Linear(Linear::constant(*v))))
+            .collect(),
+        pc: 0,
+        relative_base: 0,
+        inputs,
+        outputs: Vec::new(),
+    };
+    for (index, address) in unknowns.iter().enumerate() {
+        machine.set(*address, Value::Linear(Linear::unknown(index)));
+    }
+
+    let mut steps = 0;
+    while machine.execute()? {
+        steps += 1;
+        if steps > MAX_STEPS {
+            return Err(anyhow!("still running after {} instructions", MAX_STEPS));
+        }
+    }
+
+    let value = match observe {
+        Observe::Memory(address) => machine.get(address),
+        Observe::LastOutput => machine.outputs.pop().ok_or_else(|| anyhow!("no output"))?,
+    };
+    match value {
+        Value::Linear(linear) => Ok(linear),
+        Value::Opaque => Err(anyhow!("the result isn't linear in the unknowns")),
+    }
+}
+
+/// The first values, in order of the unknowns' ranges, for which `linear` equals `target`.
+fn solve_linear(linear: &Linear, ranges: &[Range<i64>], target: i64) -> Option<Vec<i64>> {
+    let (last, rest) = ranges.split_last()?;
+    let mut values = vec![0; ranges.len()];
+    let mut prefix: Vec<i64> = rest.iter().map(|r| r.start).collect();
+    if rest.iter().any(|r| r.start >= r.end) {
+        return None;
+    }
+
+    let coefficient = linear.coefficient(rest.len());
+    loop {
+        values[..rest.len()].copy_from_slice(&prefix);
+        values[rest.len()] = 0;
+        // Skip candidates whose value overflows.
+        let remainder = linear.eval(&values).and_then(|v| target.checked_sub(v));
+        let solution = match remainder {
+            None => None,
+            Some(remainder) if coefficient == 0 => Some(last.start).filter(|_| remainder == 0),
+            Some(remainder) => remainder
+                .checked_div(coefficient)
+                .filter(|_| remainder % coefficient == 0),
+        };
+        if let Some(x) = solution.filter(|x| last.contains(x)) {
+            values[rest.len()] = x;
+            return Some(values);
+        }
+
+        // Advance the prefix like an odometer, last digit fastest.
+        let mut i = rest.len();
+        loop {
+            if i == 0 {
+                return None;
+            }
+            i -= 1;
+            prefix[i] += 1;
+            if prefix[i] < rest[i].end {
+                break;
+            }
+            prefix[i] = rest[i].start;
+        }
+    }
+}
+
+/// How a solution was found.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Method {
+    /// Solved the expression the observed value turned out to be.
+    Symbolic(Linear),
+    /// Tried candidates, symbolic execution failed for the given reason.
+    BruteForce(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Solution {
+    /// One value per unknown.
+    pub values: Vec<i64>,
+    pub method: Method,
+}
+
+/// Find values for `unknowns` that make the observed value equal `target`.
+///
+/// Solutions are searched in order of the unknowns' ranges, so both methods agree
+/// on which one is returned when there are several.
+pub fn solve(
+    program: &[i64],
+    unknowns: &[Unknown],
+    observe: Observe,
+    target: i64,
+) -> Option<Solution> {
+    let addresses: Vec<usize> = unknowns.iter().map(|u| u.address).collect();
+    let ranges: Vec<Range<i64>> = unknowns.iter().map(|u| u.range.clone()).collect();
+
+    let run = |program: &[i64], values: &[i64]| {
+        let patch = addresses
+            .iter()
+            .zip(values)
+            .fold(Patch::new("unknowns"), |patch, (a, v)| patch.set(*a, *v));
+        let mut cpu = CPU::new(program);
+        cpu.set_limits(Limits::new().instructions(MAX_STEPS as u64));
+        cpu.apply_patch(&patch);
+        let outputs = cpu.run_to_completion()?;
+        match observe {
+            Observe::Memory(address) => Ok(cpu.get_memory(address)),
+            Observe::LastOutput => outputs.last().copied().ok_or_else(|| anyhow!("no output")),
+        }
+    };
+
+    let reason = match symbolic(program, &[], &addresses, observe) {
+        Ok(linear) => match solve_linear(&linear, &ranges, target) {
+            None => return None,
+            // Double check on a real machine before trusting the expression.
+            Some(values) if run(program, &values).ok() == Some(target) => {
+                return Some(Solution {
+                    values,
+                    method: Method::Symbolic(linear),
+                })
+            }
+            Some(values) => format!(
+                "the symbolic answer {:?} doesn't check out on a real machine",
+                values
+            ),
+        },
+        Err(e) => e.to_string(),
+    };
+
+    let space = ranges.iter().fold(vec![vec![]], |space, range| {
+        space
+            .iter()
+            .flat_map(|prefix: &Vec<i64>| {
+                range.clone().map(move |x| {
+                    let mut values = prefix.clone();
+                    values.push(x);
+                    values
+                })
+            })
+            .collect()
+    });
+    let found = search(
+        &space,
+        program,
+        Objective::Target(target),
+        |program, values| run(program, values),
+    );
+    found.best.map(|(values, _)| Solution {
+        values,
+        method: Method::BruteForce(reason),
+    })
+}
+
+#[test]
+fn test_symbolic() -> Result<()> {
+    let program = crate::intcode::read_program("input/day2.txt")?;
+    let unknowns = [
+        Unknown {
+            address: 1,
+            range: 0..100,
+        },
+        Unknown {
+            address: 2,
+            range: 0..100,
+        },
+    ];
+
+    let linear = symbolic(&program, &[], &[1, 2], Observe::Memory(0))?;
+    assert_eq!(Some(4090701), linear.eval(&[12, 2]));
+    let solution = solve(&program, &unknowns, Observe::Memory(0), 19_690_720).unwrap();
+    assert_eq!(vec![64, 21], solution.values);
+    assert_eq!(Method::Symbolic(linear), solution.method);
+    assert_eq!(None, solve(&program, &unknowns, Observe::Memory(0), -1));
+
+    // Output the product of two cells, which needs brute force.
+    let program = vec![2, 7, 8, 9, 4, 9, 99, 0, 0, 0];
+    let unknowns = [
+        Unknown {
+            address: 7,
+            range: 1..10,
+        },
+        Unknown {
+            address: 8,
+            range: 1..10,
+        },
+    ];
+    let solution = solve(&program, &unknowns, Observe::LastOutput, 12).unwrap();
+    assert_eq!(vec![2, 6], solution.values);
+    assert_eq!(
+        Method::BruteForce("the result isn't linear in the unknowns".to_string()),
+        solution.method
+    );
+
+    // Linear again once one factor is a constant.
+    let program = vec![1002, 11, 3, 13, 1, 12, 13, 13, 4, 13, 99, 0, 0, 0];
+    let linear = symbolic(&program, &[], &[11, 12], Observe::LastOutput)?;
+    assert_eq!("3*x0 + x1", linear.to_string());
+
+    // Coefficients that overflow fall back to brute force.
+    let program = vec![1002, 11, i64::MAX, 11, 1002, 11, 2, 11, 4, 11, 99, 0];
+    let unknowns = [Unknown {
+        address: 11,
+        range: 0..1,
+    }];
+    let solution = solve(&program, &unknowns, Observe::LastOutput, 0).unwrap();
+    assert_eq!(vec![0], solution.values);
+    assert_eq!(
+        Method::BruteForce("overflow at pc 4".to_string()),
+        solution.method
+    );
+
+    // Writing far away doesn't allocate everything below it.
+    let program = vec![1101, 1, 1, 1_000_000_000_000, 4, 1, 99];
+    let linear = symbolic(&program, &[], &[], Observe::Memory(1_000_000_000_000))?;
+    assert_eq!(Some(2), linear.as_constant());
+    Ok(())
+}
+
+#[test]
+fn test_solve_linear() {
+    let mut linear = Linear::unknown(0)
+        .scale(5)
+        .unwrap()
+        .add(&Linear::unknown(2).scale(-2).unwrap())
+        .unwrap();
+    linear.constant = 7;
+    assert_eq!("5*x0 - 2*x2 + 7", linear.to_string());
+    assert_eq!(
+        Some(vec![0, 0, 1]),
+        solve_linear(&linear, &[0..3, 0..3, 0..3], 5)
+    );
+    assert_eq!(
+        Some(vec![1, 0, 2]),
+        solve_linear(&linear, &[1..3, 0..3, 0..3], 8)
+    );
+    assert_eq!(None, solve_linear(&linear, &[0..3, 0..3, 0..3], 100));
+    assert_eq!(None, solve_linear(&linear, &[], 7));
+}
@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::intcode::device::Writer;
+use crate::intcode::disasm::{decode, Op};
+
+/// A single executed instruction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Trace {
+    pub pc: usize,
+    pub opcode: u8,
+    /// The values read by the instruction, after resolving parameter modes.
+    pub operands: Vec<i64>,
+    /// The address written to and the value stored there.
+    pub write: Option<(usize, i64)>,
+}
+
+/// A sink for executed instructions, see `CPU::set_tracer`.
+pub trait Tracer {
+    fn trace(&mut self, trace: &Trace);
+}
+
+impl Tracer for Vec<Trace> {
+    fn trace(&mut self, trace: &Trace) {
+        self.push(trace.clone());
+    }
+}
+
+/// Lets the caller hold on to a tracer while the CPU owns it.
+impl<T: Tracer> Tracer for Rc<RefCell<T>> {
+    fn trace(&mut self, trace: &Trace) {
+        self.borrow_mut().trace(trace);
+    }
+}
+
+fn mnemonic(opcode: u8) -> &'static str {
+    Op::from_opcode(opcode).map_or("???", |op| op.mnemonic())
+}
+
+/// Writes one line per executed instruction, e.g. `   12: ADD 3, 4 -> [15] = 7`.
+pub struct TraceLog<W: Write> {
+    out: Writer<W>,
+}
+
+impl<W: Write> TraceLog<W> {
+    pub fn new(out: W) -> TraceLog<W> {
+        TraceLog {
+            out: Writer::new(out),
+        }
+    }
+
+    /// See `Writer::finish`.
+    pub fn finish(self) -> io::Result<W> {
+        self.out.finish()
+    }
+}
+
+impl<W: Write> Tracer for TraceLog<W> {
+    fn trace(&mut self, trace: &Trace) {
+        let operands: Vec<String> = trace.operands.iter().map(|o| o.to_string()).collect();
+        let mut line = format!("{:>5}: {}", trace.pc, mnemonic(trace.opcode));
+        if !operands.is_empty() {
+            write!(line, " {}", operands.join(", ")).unwrap();
+        }
+        if let Some((address, value)) = trace.write {
+            write!(line, " -> [{}] = {}", address, value).unwrap();
+        }
+        self.out.line(line);
+    }
+}
+
+/// Counts how often each instruction and each opcode is executed.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    total: u64,
+    by_pc: HashMap<usize, u64>,
+    by_opcode: BTreeMap<u8, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn hits(&self, pc: usize) -> u64 {
+        self.by_pc.get(&pc).copied().unwrap_or(0)
+    }
+
+    pub fn opcode_hits(&self, opcode: u8) -> u64 {
+        self.by_opcode.get(&opcode).copied().unwrap_or(0)
+    }
+
+    /// The `count` most executed instructions, hottest first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut spots: Vec<(usize, u64)> = self.by_pc.iter().map(|(pc, n)| (*pc, *n)).collect();
+        spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        spots.truncate(count);
+        spots
+    }
+
+    /// Render opcode counts and the `count` hottest instructions of `program`.
+    pub fn report(&self, program: &[i64], count: usize) -> String {
+        let percent = |n: u64| 100.0 * n as f64 / self.total.max(1) as f64;
+
+        let mut out = format!("{} instructions executed\n\nby opcode:\n", self.total);
+        let mut opcodes: Vec<(&u8, &u64)> = self.by_opcode.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (opcode, n) in opcodes {
+            writeln!(
+                out,
+                "  {:<4} {:>12} {:>6.2}%",
+                mnemonic(*opcode),
+                n,
+                percent(*n)
+            )
+            .unwrap();
+        }
+
+        out.push_str("\nhot spots:\n");
+        for (pc, n) in self.hot_spots(count) {
+            let line = decode(program, pc).map_or("???".to_string(), |l| l.to_string());
+            writeln!(out, "  {:>5}: {:>12} {:>6.2}%  {}", pc, n, percent(n), line).unwrap();
+        }
+        out
+    }
+}
+
+impl Tracer for Profiler {
+    fn trace(&mut self, trace: &Trace) {
+        self.total += 1;
+        *self.by_pc.entry(trace.pc).or_insert(0) += 1;
+        *self.by_opcode.entry(trace.opcode).or_insert(0) += 1;
+    }
+}
+
+#[test]
+fn test_trace() -> anyhow::Result<()> {
+    use crate::intcode::{Status, CPU};
+
+    let traces = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = CPU::new(&[3, 9, 1002, 9, 3, 9, 4, 9, 99, 0]);
+    cpu.set_tracer(Box::new(traces.clone()));
+    cpu.add_input(7);
+    assert_eq!(Status::Ready(21), cpu.step()?);
+    assert_eq!(Status::Halted, cpu.step()?);
+
+    let expected = vec![
+        Trace {
+            pc: 0,
+            opcode: 3,
+            operands: vec![],
+            write: Some((9, 7)),
+        },
+        Trace {
+            pc: 2,
+            opcode: 2,
+            operands: vec![7, 3],
+            write: Some((9, 21)),
+        },
+        Trace {
+            pc: 6,
+            opcode: 4,
+            operands: vec![21],
+            write: None,
+        },
+        Trace {
+            pc: 8,
+            opcode: 99,
+            operands: vec![],
+            write: None,
+        },
+    ];
+    assert_eq!(expected, *traces.borrow());
+
+    let mut log = TraceLog::new(Vec::new());
+    for trace in traces.borrow().iter() {
+        log.trace(trace);
+    }
+    assert_eq!(
+        "    0: IN -> [9] = 7\n    2: MUL 7, 3 -> [9] = 21\n    6: OUT 21\n    8: HLT\n",
+        String::from_utf8(log.finish()?)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_profiler() -> anyhow::Result<()> {
+    use crate::intcode::{asm::assemble, Status, CPU};
+
+    let program = assemble(
+        "
+        loop:   ADD [count], #1 -> [count]
+                LT [count], #10 -> [flag]
+                JNZ [flag], #loop
+                HLT
+        count:  DB 0
+        flag:   DB 0
+        ",
+    )?;
+
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    let mut cpu = CPU::new(&program);
+    cpu.set_tracer(Box::new(profiler.clone()));
+    assert_eq!(Status::Halted, cpu.step()?);
+
+    let profiler = profiler.borrow();
+    assert_eq!(31, profiler.total());
+    assert_eq!(10, profiler.hits(0));
+    assert_eq!(10, profiler.opcode_hits(7));
+    assert_eq!(1, profiler.opcode_hits(99));
+    assert_eq!(vec![(0, 10), (4, 10)], profiler.hot_spots(2));
+    assert!(profiler
+        .report(&program, 1)
+        .contains("0:           10  32.26%  ADD [12], #1 -> [12]"));
+    Ok(())
+}